@@ -8,7 +8,7 @@ use std::sync::{ Arc, Mutex };
 use rayon::prelude::*;
 use image::{ Rgb, RgbImage };
 
-use raytracing_study::{ vec3, Vector3, math };
+use raytracing_study::{ vec3, Vector3, HdrImage, math };
 use raytracing_study::{ Camera, Ray, Scene, PPrimitive, Geometry, Sphere, Rect };
 use raytracing_study::{ LambertMaterial, MicrofacetReflectionMaterial };
 
@@ -43,7 +43,7 @@ fn main() {
                     for _s in 0..samples {
                         let x = (x + (rand::random::<f64>() + (sx as f64)) * inv_subpixel) / (width as f64);
                         let y = (y + (rand::random::<f64>() + (sy as f64)) * inv_subpixel) / (height as f64);
-                        let ray = camera.get_ray(x, y);
+                        let ray = camera.get_ray_differential(x, y, inv_subpixel / (width as f64), inv_subpixel / (height as f64));
                         subsum += render(&ray, &scene, &background, 0);
                     }
                     sum += subsum  / (samples as f64);
@@ -71,7 +71,7 @@ fn render(ray: &Ray, scene: &Scene, background: &Background, depth: u32) -> Vect
     scene.hit(ray).map_or(background.sample(ray.dir.norm()), |(isec, material)| {
         material.sample(&isec).map_or(Vector3::zero(), |bsdf| {
             if bsdf.pdf != 0.0 {
-                let next_ray = Ray::new(isec.pos, bsdf.wi);
+                let next_ray = isec.spawn_ray(ray, bsdf.wi, bsdf.specular);
                 let dot = isec.normal.dot(bsdf.wi).abs();
                 bsdf.value * dot * render(&next_ray, scene, background, depth + 1) / bsdf.pdf
             } else {
@@ -122,24 +122,20 @@ fn create_camera(width: u32, height: u32) -> Camera {
     camera
 }
 
+// environment map of .hdr, .pfm, .exr or 8-bit image in sRGB
 struct Background {
-    image: image::RgbImage,
+    image: HdrImage,
 }
 
 impl Background {
     pub fn new(filename: &str) -> Background {
-        let image = image::open(filename).unwrap().to_rgb();
+        let image = HdrImage::open(filename, math::gamma_to_linear);
         Background { image }
     }
     pub fn sample(&self, w: Vector3) -> Vector3 {
         let uv = math::sphere_uv(w);
-        let pixel = self.image.get_pixel(
-            (uv.0 * (self.image.width() as f64)) as u32,
-            ((1.0 - uv.1) * (self.image.height() as f64)) as u32);
-        vec3(
-            math::gamma_to_linear(pixel[0] as f64 / 255.0),
-            math::gamma_to_linear(pixel[1] as f64 / 255.0),
-            math::gamma_to_linear(pixel[2] as f64 / 255.0)
-        )
+        let x = (uv.0 * (self.image.width() as f64)) as usize;
+        let y = ((1.0 - uv.1) * (self.image.height() as f64)) as usize;
+        self.image.get(x.min(self.image.width() - 1), y.min(self.image.height() - 1))
     }
 }
\ No newline at end of file
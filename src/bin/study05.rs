@@ -41,7 +41,7 @@ fn main() {
                     for _s in 0..samples {
                         let x = (x + (rand::random::<f64>() + (sx as f64)) * inv_subpixel) / (width as f64);
                         let y = (y + (rand::random::<f64>() + (sy as f64)) * inv_subpixel) / (height as f64);
-                        let ray = camera.get_ray(x, y);
+                        let ray = camera.get_ray_differential(x, y, inv_subpixel / (width as f64), inv_subpixel / (height as f64));
                         subsum += render(&ray, &scene, 0);
                     }
                     sum += subsum  / (samples as f64);
@@ -69,7 +69,7 @@ fn render(ray: &Ray, scene: &Scene, depth: u32) -> Vector3 {
     scene.hit(ray).map_or(vec3(0.0, 0.0, 0.0), |(isec, material)| {
         material.sample(&isec).map_or(material.emit(&isec), |bsdf| {
             if bsdf.pdf != 0.0 {
-                let next_ray = Ray::new(isec.pos, bsdf.wi);
+                let next_ray = isec.spawn_ray(ray, bsdf.wi, bsdf.specular);
                 let dot = isec.normal.dot(bsdf.wi).abs();
                 material.emit(&isec) + bsdf.value * dot * render(&next_ray, scene, depth + 1) / bsdf.pdf
             } else {
@@ -42,7 +42,7 @@ fn main() {
                     for _s in 0..samples {
                         let x = (x + (rand::random::<f64>() + (sx as f64)) * inv_subpixel) / (width as f64);
                         let y = (y + (rand::random::<f64>() + (sy as f64)) * inv_subpixel) / (height as f64);
-                        let ray = camera.get_ray(x, y);
+                        let ray = camera.get_ray_differential(x, y, inv_subpixel / (width as f64), inv_subpixel / (height as f64));
                         subsum += render(&ray, &scene, &light);
                     }
                     sum += subsum  / (samples as f64);
@@ -101,7 +101,7 @@ fn render(ray: &Ray, scene: &Scene, light: &Box<PPrimitive>) -> Vector3 {
                             break;
                         }
                         weight /= p;
-                        ray = Ray::new(isec.pos, bsdf.wi);
+                        ray = isec.spawn_ray(&ray, bsdf.wi, bsdf.specular);
                     }
                 }
             }
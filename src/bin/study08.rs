@@ -0,0 +1,173 @@
+extern crate rayon;
+extern crate image;
+extern crate rand;
+extern crate raytracing_study;
+
+use std::path::Path;
+use std::sync::{ Arc, Mutex };
+
+use rayon::prelude::*;
+use image::{ Rgb, RgbImage };
+
+use raytracing_study::{ vec3, Vector3, Transform, HdrImage, math, util };
+use raytracing_study::{ Camera, Ray, Scene, PPrimitive, Geometry, TransformedPrimitive, Rect, Sphere };
+use raytracing_study::{ LambertMaterial };
+use raytracing_study::{ PLight, PointLight, SpotLight };
+
+fn main() {
+    let (width, height) = (800, 800);
+
+    let subpixel = 4;
+    let inv_subpixel = 1.0 / (subpixel as f64);
+    let samples = 5;
+
+    let scene = create_scene();
+    let lights = create_lights();
+    let camera = create_camera(width, height);
+
+    let mut image = RgbImage::new(width, height);
+
+    let progress = Mutex::new(0u32);
+    // unclamped radiance of each pixel in order of rows from top
+    let radiance = image.enumerate_pixels_mut()
+        .collect::<Vec<(u32, u32, &mut Rgb<u8>)>>()
+        .par_iter_mut()
+        .map(|(x, y, pixel)| {
+            let x = *x as f64;
+            let y = (height - *y - 1) as f64;
+
+            let mut sum = Vector3::zero();
+            for sx in 0..subpixel {
+                for sy in 0..subpixel {
+                    let mut subsum = Vector3::zero();
+                    for _s in 0..samples {
+                        let x = (x + (rand::random::<f64>() + (sx as f64)) * inv_subpixel) / (width as f64);
+                        let y = (y + (rand::random::<f64>() + (sy as f64)) * inv_subpixel) / (height as f64);
+                        let ray = camera.get_ray_differential(x, y, inv_subpixel / (width as f64), inv_subpixel / (height as f64));
+                        subsum += render(&ray, &scene, &lights);
+                    }
+                    sum += subsum  / (samples as f64);
+                }
+            }
+            sum /= (subpixel * subpixel) as f64;
+
+            pixel[0] = (math::linear_to_gamma(math::clamp(sum.x, 0.0, 1.0)) * 255.99) as u8;
+            pixel[1] = (math::linear_to_gamma(math::clamp(sum.y, 0.0, 1.0)) * 255.99) as u8;
+            pixel[2] = (math::linear_to_gamma(math::clamp(sum.z, 0.0, 1.0)) * 255.99) as u8;
+            let mut progress = progress.lock().unwrap();
+            *progress += 1;
+            if (*progress).is_multiple_of(width) {
+                println!("progress of rendering: {:.2}%", 100.0 * (*progress as f64) / ((width * height) as f64));
+            }
+            sum
+        })
+        .collect::<Vec<Vector3>>();
+
+    image.save("./outputs/study08.jpg").unwrap();
+    let mut hdr = HdrImage::new(width as usize, height as usize);
+    for (i, value) in radiance.into_iter().enumerate() {
+        hdr.set(i % width as usize, i / width as usize, value);
+    }
+    hdr.save("./outputs/study08.exr");
+}
+
+fn render(ray: &Ray, scene: &Scene, lights: &[Box<PLight>]) -> Vector3 {
+    let max_bounce = 50;
+
+    let mut ray = (*ray).clone();
+    let mut result = Vector3::zero();
+    let mut weight = Vector3::one();
+    for _bounce in 0..max_bounce {
+        match scene.hit(&ray) {
+            None => break,
+            Some((isec, material)) => {
+                match material.sample(&isec) {
+                    None => break,
+                    Some(bsdf) => {
+                        for light in lights {
+                            if let Some(light_sample) = light.sample(isec.pos) {
+                                let light_pos = isec.pos + light_sample.dist * light_sample.wi;
+                                if scene.visible(isec.pos, light_pos) {
+                                    let dot = isec.normal.dot(light_sample.wi).max(0.0);
+                                    result += weight * material.bsdf(&isec, light_sample.wi) * light_sample.value * dot / light_sample.pdf;
+                                }
+                            }
+                        }
+
+                        let dot = isec.normal.dot(bsdf.wi).abs();
+                        weight *= bsdf.value * dot / bsdf.pdf;
+                        // russian roulette
+                        let p = weight.x.max(weight.y.max(weight.z));
+                        if rand::random::<f64>() > p {
+                            break;
+                        }
+                        weight /= p;
+                        ray = isec.spawn_ray(&ray, bsdf.wi, bsdf.specular);
+                    }
+                }
+            }
+        }
+    }
+    result
+}
+
+fn create_scene() -> Scene {
+    let mut primitives: Vec<Box<PPrimitive>> = Vec::new();
+
+    // cornel box
+    let white_mat = Arc::new(LambertMaterial::new(vec3(0.95, 0.95, 0.95)));
+    let red_mat = Arc::new(LambertMaterial::new(vec3(0.95, 0.1, 0.1)));
+    let green_mat = Arc::new(LambertMaterial::new(vec3(0.1, 0.95, 0.1)));
+
+    let bottom_prim = Geometry::new(Box::new(Rect::new(10.0, 10.0)), white_mat.clone());
+
+    let top_prim = Geometry::new(Box::new(Rect::new(10.0, 10.0)), white_mat.clone());
+    let top_prim = TransformedPrimitive::new(Box::new(top_prim), Transform::rotate_x(-180.0).transform(&Transform::translate(0.0, 10.0, 0.0)));
+
+    let far_prim = Geometry::new(Box::new(Rect::new(10.0, 10.0)), white_mat.clone());
+    let far_prim = TransformedPrimitive::new(Box::new(far_prim), Transform::rotate_x(-90.0).transform(&Transform::translate(0.0, 5.0, 5.0)));
+
+    let left_prim = Geometry::new(Box::new(Rect::new(10.0, 10.0)), green_mat.clone());
+    let left_prim = TransformedPrimitive::new(Box::new(left_prim), Transform::rotate_z(-90.0).transform(&Transform::translate(-5.0, 5.0, 0.0)));
+
+    let right_prim = Geometry::new(Box::new(Rect::new(10.0, 10.0)), red_mat.clone());
+    let right_prim = TransformedPrimitive::new(Box::new(right_prim), Transform::rotate_z(90.0).transform(&Transform::translate(5.0, 5.0, 0.0)));
+
+    primitives.push(Box::new(bottom_prim));
+    primitives.push(Box::new(top_prim));
+    primitives.push(Box::new(far_prim));
+    primitives.push(Box::new(left_prim));
+    primitives.push(Box::new(right_prim));
+
+    let sphere_mat = Arc::new(LambertMaterial::new(vec3(0.3, 0.3, 0.3)));
+    let sphere = Geometry::new(Box::new(Sphere::new(vec3(0.0, 2.5, 0.0), 2.5)), sphere_mat.clone());
+    primitives.push(Box::new(sphere));
+
+    Scene::new(primitives)
+}
+
+fn create_lights() -> Vec<Box<PLight>> {
+    let mut lights: Vec<Box<PLight>> = Vec::new();
+
+    // downlight with photometric profile, or plain spot light if the profile is not available
+    let ies_file = "./resources/ies/downlight.ies";
+    let spot_light = if Path::new(ies_file).exists() {
+        let profile = util::load_ies(ies_file);
+        SpotLight::with_profile(vec3(60.0, 60.0, 60.0), 60.0, 45.0, profile, Transform::translate(-2.0, 9.5, 0.0))
+    } else {
+        SpotLight::new(vec3(60.0, 60.0, 60.0), 60.0, 45.0, Transform::translate(-2.0, 9.5, 0.0))
+    };
+    lights.push(Box::new(spot_light));
+
+    let point_light = PointLight::new(vec3(8.0, 7.0, 5.0), Transform::translate(3.0, 7.0, -3.0));
+    lights.push(Box::new(point_light));
+
+    lights
+}
+
+fn create_camera(width: u32, height: u32) -> Camera {
+    let cam_origin = vec3(0.0, 5.0, -14.0);
+    let cam_target = vec3(0.0, 5.0, 0.0);
+    let cam_up = vec3(0.0, 1.0, 0.0);
+    Camera::look_at(cam_origin, cam_target, cam_up, 60.0, (width as f64) / (height as f64))
+}
@@ -1,8 +1,7 @@
 use std::sync::Arc;
 
-use rand::random;
-
 use crate::Vector3;
+use crate::math;
 use crate::{ Ray, Primitive, PPrimitive, Intersection, PMaterial, Aabb };
 
 enum BvhNode {
@@ -18,7 +17,7 @@ impl BvhNode {
             return BvhNode::Leaf(primitives.remove(0));
         }
 
-        let axis = (random::<f64>() * 3.0) as usize;
+        let axis = (math::random() * 3.0) as usize;
         primitives.sort_by(|a, b| a.aabb().center()[axis].partial_cmp(&b.aabb().center()[axis]).unwrap());
 
         let primitives2 = primitives.split_off(primitives.len() / 2);
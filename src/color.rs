@@ -0,0 +1,63 @@
+use crate::{ vec3, Vector3 };
+
+// maximum luminous efficacy of radiation [lm/W]
+pub const LUMINOUS_EFFICACY: f64 = 683.0;
+
+pub fn luminance(rgb: Vector3) -> f64 {
+    0.2126 * rgb.x + 0.7152 * rgb.y + 0.0722 * rgb.z
+}
+
+// scales color so that its luminance is one
+pub fn normalize_luminance(rgb: Vector3) -> Vector3 {
+    let y = luminance(rgb);
+    if y > 0.0 { rgb / y } else { Vector3::zero() }
+}
+
+// decodes transfer function of sRGB to linear value
+pub fn srgb_to_linear(v: f64) -> f64 {
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub fn xyz_to_rgb(xyz: Vector3) -> Vector3 {
+    vec3(
+        3.2406 * xyz.x - 1.5372 * xyz.y - 0.4986 * xyz.z,
+        -0.9689 * xyz.x + 1.8758 * xyz.y + 0.0415 * xyz.z,
+        0.0557 * xyz.x - 0.2040 * xyz.y + 1.0570 * xyz.z
+    )
+}
+
+// linear sRGB color of black body radiation whose luminance is one
+pub fn blackbody(kelvin: f64) -> Vector3 {
+    let mut xyz = Vector3::zero();
+    let mut lambda = 380.0;
+    while lambda <= 780.0 {
+        xyz += planck(lambda, kelvin) * cie_color_matching(lambda);
+        lambda += 5.0;
+    }
+    let rgb = xyz_to_rgb(xyz / xyz.y);
+    normalize_luminance(Vector3::max(rgb, Vector3::zero()))
+}
+
+// spectral radiance of black body without constant factor
+fn planck(lambda: f64, kelvin: f64) -> f64 {
+    let c2 = 1.4387769e-2;
+    let l = lambda * 1.0e-9;
+    1.0 / (l.powi(5) * ((c2 / (l * kelvin)).exp() - 1.0))
+}
+
+// multi-lobe gaussian fit of CIE 1931 color matching functions by Wyman et al.
+fn cie_color_matching(lambda: f64) -> Vector3 {
+    let g = |mu: f64, sigma1: f64, sigma2: f64| {
+        let t = (lambda - mu) / if lambda < mu { sigma1 } else { sigma2 };
+        (-0.5 * t * t).exp()
+    };
+    vec3(
+        1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2),
+        0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1),
+        1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8)
+    )
+}
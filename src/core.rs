@@ -1,19 +1,35 @@
 use std::sync::Arc;
 
-use crate::Vector3;
+use crate::{ vec3, Vector3 };
 use crate::PShape;
 use crate::PMaterial;
+use crate::PTexture;
 use crate::Transform;
+use crate::math;
+use crate::{ IlluminantMaterial, LightUnit };
+
+// rays offset toward neighboring pixels in x and y, which estimate footprint of pixel on surfaces
+#[derive(Debug, Clone)]
+pub struct RayDifferential {
+    pub rx_org: Vector3,
+    pub rx_dir: Vector3,
+    pub ry_org: Vector3,
+    pub ry_dir: Vector3,
+}
 
 #[derive(Debug, Clone)]
 pub struct Ray {
     pub org: Vector3,
     pub dir: Vector3,
+    pub differential: Option<RayDifferential>,
 }
 
 impl Ray {
     pub fn new(org: Vector3, dir: Vector3) -> Ray {
-        Ray { org, dir }
+        Ray { org, dir, differential: None }
+    }
+    pub fn with_differential(org: Vector3, dir: Vector3, differential: RayDifferential) -> Ray {
+        Ray { org, dir, differential: Some(differential) }
     }
     pub fn at(&self, t: f64) -> Vector3 {
         self.org + t * self.dir
@@ -24,7 +40,121 @@ pub struct Intersection {
     pub t: f64,
     pub wo: Vector3,
     pub pos: Vector3,
+    // position in space of shape, which is kept through TransformedPrimitive
+    pub object_pos: Vector3,
+    // normal of shading frame, which may differ from normal of actual surface
     pub normal: Vector3,
+    pub geometric_normal: Vector3,
+    // shading normal in space of shape, which is kept through TransformedPrimitive
+    pub object_normal: Vector3,
+    // footprint of pixel, which is computed by Scene::hit for ray with differential
+    pub differential: Option<SurfaceDifferential>,
+    pub uv: (f64, f64),
+    // partial derivatives of position with respect to uv
+    pub dpdu: Vector3,
+    pub dpdv: Vector3,
+    // partial derivatives in space of shape, which are kept through TransformedPrimitive
+    pub object_dpdu: Vector3,
+    pub object_dpdv: Vector3,
+    pub tangent: Vector3,
+}
+
+// change of position and texture coordinates toward neighboring pixels in x and y
+#[derive(Debug, Clone, Copy)]
+pub struct SurfaceDifferential {
+    pub dpdx: Vector3,
+    pub dpdy: Vector3,
+    pub duvdx: (f64, f64),
+    pub duvdy: (f64, f64),
+}
+
+impl Intersection {
+    // intersection in space of shape whose geometric normal is the same as shading normal.
+    // shapes override the other fields by struct update
+    pub fn new(t: f64, wo: Vector3, pos: Vector3, normal: Vector3, uv: (f64, f64), dpdu: Vector3, dpdv: Vector3) -> Intersection {
+        Intersection {
+            t,
+            wo,
+            pos,
+            object_pos: pos,
+            normal,
+            geometric_normal: normal,
+            object_normal: normal,
+            differential: None,
+            uv,
+            dpdu,
+            dpdv,
+            object_dpdu: dpdu,
+            object_dpdv: dpdv,
+            tangent: math::tangent(normal, dpdu),
+        }
+    }
+    pub fn bitangent(&self) -> Vector3 {
+        self.normal.cross(self.tangent)
+    }
+    // convert to local space whose axes are tangent, bitangent and normal
+    pub fn to_local(&self, v: Vector3) -> Vector3 {
+        vec3(v.dot(self.tangent), v.dot(self.bitangent()), v.dot(self.normal))
+    }
+    pub fn to_world(&self, v: Vector3) -> Vector3 {
+        v.x * self.tangent + v.y * self.bitangent() + v.z * self.normal
+    }
+    // intersects offset rays with tangent plane and projects the offsets onto dpdu and dpdv
+    pub fn compute_differential(&self, differential: &RayDifferential) -> Option<SurfaceDifferential> {
+        let n = self.geometric_normal;
+        let d = n.dot(self.pos);
+        let (nx, ny) = (n.dot(differential.rx_dir), n.dot(differential.ry_dir));
+        if nx == 0.0 || ny == 0.0 {
+            return None;
+        }
+        let px = differential.rx_org + (d - n.dot(differential.rx_org)) / nx * differential.rx_dir;
+        let py = differential.ry_org + (d - n.dot(differential.ry_org)) / ny * differential.ry_dir;
+        let (dpdx, dpdy) = (px - self.pos, py - self.pos);
+
+        // least squares in the two axes where the plane has the largest projection
+        let (a0, a1) = if n.x.abs() > n.y.abs() && n.x.abs() > n.z.abs() {
+            (1, 2)
+        } else if n.y.abs() > n.z.abs() {
+            (0, 2)
+        } else {
+            (0, 1)
+        };
+        let det = self.dpdu[a0] * self.dpdv[a1] - self.dpdv[a0] * self.dpdu[a1];
+        let solve = |v: Vector3| {
+            if det.abs() < 1e-12 {
+                (0.0, 0.0)
+            } else {
+                ((self.dpdv[a1] * v[a0] - self.dpdv[a0] * v[a1]) / det, (self.dpdu[a0] * v[a1] - self.dpdu[a1] * v[a0]) / det)
+            }
+        };
+        Some(SurfaceDifferential { dpdx, dpdy, duvdx: solve(dpdx), duvdy: solve(dpdy) })
+    }
+    // ray scattered toward wi. differential is carried through specular bounce only,
+    // assuming the surface is locally flat
+    pub fn spawn_ray(&self, ray: &Ray, wi: Vector3, specular: bool) -> Ray {
+        let (differential, surface) = match (&ray.differential, &self.differential) {
+            (Some(differential), Some(surface)) if specular => (differential, surface),
+            _ => return Ray::new(self.pos, wi),
+        };
+        let n = if self.normal.dot(self.wo) < 0.0 { -self.normal } else { self.normal };
+        let scatter = |dir: Vector3| {
+            if n.dot(wi) > 0.0 {
+                math::reflect(dir, n)
+            } else {
+                // relative index of refraction is recovered from sines of both directions
+                let sin_o = self.wo.cross(n).mag();
+                let sin_i = wi.cross(n).mag();
+                let ri = if sin_o > 1e-6 { sin_i / sin_o } else { 1.0 };
+                math::refract(-dir.norm(), n, ri).unwrap_or(wi)
+            }
+        };
+        Ray::with_differential(self.pos, wi, RayDifferential {
+            rx_org: self.pos + surface.dpdx,
+            rx_dir: scatter(differential.rx_dir),
+            ry_org: self.pos + surface.dpdy,
+            ry_dir: scatter(differential.ry_dir),
+        })
+    }
 }
 
 pub struct Scene {
@@ -36,11 +166,21 @@ impl Scene {
         Scene { primitives }
     }
     pub fn hit(&self, ray: &Ray) -> Option<(Intersection, Arc<PMaterial>)> {
-        self.primitives.iter().fold(None, |res, primitive| {
+        let hit = self.primitives.iter().fold(None, |res: Option<(Intersection, Arc<PMaterial>)>, primitive| {
             let tmax = res.as_ref().map_or(std::f64::MAX, |res| res.0.t);
             primitive.hit(ray, 1.0e-6, tmax).or(res)
+        });
+        // footprint and shading frame are set up only for the closest hit
+        hit.map(|(isec, material)| {
+            let differential = ray.differential.as_ref().and_then(|differential| isec.compute_differential(differential));
+            let isec = Intersection { differential, ..isec };
+            (material.shade(&isec), material)
         })
     }
+    pub fn visible(&self, from: Vector3, to: Vector3) -> bool {
+        let ray = Ray::new(from, to - from);
+        self.primitives.iter().all(|primitive| primitive.hit(&ray, 1.0e-6, 1.0 - 1.0e-6).is_none())
+    }
 }
 
 pub trait Primitive {
@@ -53,20 +193,58 @@ pub trait Primitive {
 
 pub type PPrimitive = dyn Primitive + Sync + 'static;
 
+// opacity mask of geometry like leaves or fences
+pub enum Alpha {
+    // surface is cut out where alpha is below the threshold
+    Cutout(Arc<PTexture<f64>>, f64),
+    // surface is skipped randomly with probability of one minus alpha
+    Stochastic(Arc<PTexture<f64>>),
+}
+
+impl Alpha {
+    fn is_opaque(&self, isec: &Intersection) -> bool {
+        match self {
+            Alpha::Cutout(texture, threshold) => texture.value(isec) >= *threshold,
+            Alpha::Stochastic(texture) => {
+                let alpha = texture.value(isec);
+                alpha >= 1.0 || (alpha > 0.0 && math::random() < alpha)
+            }
+        }
+    }
+}
+
 pub struct Geometry {
     shape: Box<PShape>,
     material: Arc<PMaterial>,
+    alpha: Option<Alpha>,
 }
 
 impl Geometry {
     pub fn new(shape: Box<PShape>, material: Arc<PMaterial>) -> Geometry {
-        Geometry { shape, material }
+        Geometry { shape, material, alpha: None }
+    }
+    pub fn with_alpha(shape: Box<PShape>, material: Arc<PMaterial>, alpha: Alpha) -> Geometry {
+        Geometry { shape, material, alpha: Some(alpha) }
+    }
+    // emitter whose total power does not depend on its size.
+    // area is measured in space of shape, so use TransformedPrimitive::emitter to scale it
+    pub fn emitter(shape: Box<PShape>, color: Vector3, unit: LightUnit, two_sided: bool) -> Geometry {
+        let material = IlluminantMaterial::with_unit(color, unit, shape.area(), two_sided);
+        Geometry::new(shape, Arc::new(material))
     }
 }
 
 impl Primitive for Geometry {
     fn hit(&self, ray: &Ray, tmin: f64, tmax: f64) -> Option<(Intersection, Arc<PMaterial>)> {
-        self.shape.hit(ray, tmin, tmax).map(|isec| (isec, self.material.clone()))
+        let mut tmin = tmin;
+        loop {
+            let isec = self.shape.hit(ray, tmin, tmax)?;
+            match &self.alpha {
+                // continue to find next hit behind transparent part
+                Some(alpha) if !alpha.is_opaque(&isec) => tmin = isec.t,
+                _ => return Some((isec, self.material.clone())),
+            }
+        }
     }
     fn aabb(&self) -> &Aabb {
         self.shape.aabb()
@@ -94,17 +272,28 @@ impl TransformedPrimitive {
         let aabb = transform.aabb(primitive.aabb());
         TransformedPrimitive { primitive, transform, inv_transform, aabb }
     }
+    // emitter whose total power does not depend on its size after the transform
+    pub fn emitter(shape: Box<PShape>, color: Vector3, unit: LightUnit, two_sided: bool, transform: Transform) -> TransformedPrimitive {
+        let material = IlluminantMaterial::with_unit(color, unit, shape.transformed_area(&transform), two_sided);
+        TransformedPrimitive::new(Box::new(Geometry::new(shape, Arc::new(material))), transform)
+    }
 }
 
 impl Primitive for TransformedPrimitive {
     fn hit(&self, ray: &Ray, tmin: f64, tmax: f64) -> Option<(Intersection, Arc<PMaterial>)> {
         let inv_ray = self.inv_transform.ray(ray);
         self.primitive.hit(&inv_ray, tmin, tmax).map(|(isec, material)| {
+            let normal = self.transform.normal(isec.normal).norm();
+            let geometric_normal = self.transform.normal(isec.geometric_normal).norm();
             (Intersection {
-                t: isec.t,
                 wo: self.transform.vector(isec.wo),
                 pos: self.transform.point(isec.pos),
-                normal: self.transform.normal(isec.normal),
+                normal,
+                geometric_normal,
+                dpdu: self.transform.vector(isec.dpdu),
+                dpdv: self.transform.vector(isec.dpdv),
+                tangent: math::tangent(normal, self.transform.vector(isec.tangent)),
+                ..isec
             }, material)
         })
     }
@@ -133,6 +322,17 @@ impl Camera {
         let v = 2.0 * v - 1.0;
         Ray::new(self.origin, self.basis.0 * u + self.basis.1 * v + self.basis.2)
     }
+    // ray with differential toward the next pixel, where du and dv are size of pixel like 1 / width
+    pub fn get_ray_differential(&self, u: f64, v: f64, du: f64, dv: f64) -> Ray {
+        let ray = self.get_ray(u, v);
+        let differential = RayDifferential {
+            rx_org: self.origin,
+            rx_dir: ray.dir + 2.0 * du * self.basis.0,
+            ry_org: self.origin,
+            ry_dir: ray.dir + 2.0 * dv * self.basis.1,
+        };
+        Ray::with_differential(ray.org, ray.dir, differential)
+    }
 }
 
 pub struct Aabb {
@@ -166,7 +366,8 @@ impl Aabb {
             let t1 = (self.max[i] - ray.org[i]) / ray.dir[i];
             tmin = t0.min(t1).max(tmin);
             tmax = t0.max(t1).min(tmax);
-            if tmax <= tmin {
+            // box can be flat along an axis like axis aligned triangle
+            if tmax < tmin {
                 return false;
             }
         }
@@ -0,0 +1,280 @@
+use std::sync::Arc;
+
+use crate::{ vec3, Vector3 };
+use crate::Intersection;
+use crate::material::{ Material, Bsdf };
+use crate::PTexture;
+use crate::texture::constant;
+use crate::{ color, math };
+
+// number of lobes evaluated explicitly: R, TT and TRT. higher order scattering is lumped into the last one.
+const P_MAX: usize = 3;
+
+// hair fiber scattering model by d'Eon et al. and Chiang et al.
+// local x axis is along the fiber and v of texture coordinates is offset across the fiber width.
+pub struct HairMaterial {
+    sigma_a: Arc<PTexture<Vector3>>,
+    beta_m: Arc<PTexture<f64>>,
+    beta_n: Arc<PTexture<f64>>,
+    alpha: Arc<PTexture<f64>>,
+}
+
+impl HairMaterial {
+    // beta_m and beta_n are longitudinal and azimuthal roughness, alpha is tilt of cuticle scales in degrees
+    pub fn new(sigma_a: Vector3, beta_m: f64, beta_n: f64, alpha: f64) -> HairMaterial {
+        HairMaterial::textured(constant(sigma_a), constant(beta_m), constant(beta_n), constant(alpha))
+    }
+    pub fn textured(sigma_a: Arc<PTexture<Vector3>>, beta_m: Arc<PTexture<f64>>, beta_n: Arc<PTexture<f64>>, alpha: Arc<PTexture<f64>>) -> HairMaterial {
+        HairMaterial { sigma_a, beta_m, beta_n, alpha }
+    }
+    // absorption by concentrations of eumelanin and pheomelanin pigments
+    pub fn melanin(eumelanin: f64, pheomelanin: f64, beta_m: f64, beta_n: f64, alpha: f64) -> HairMaterial {
+        let sigma_a = eumelanin * vec3(0.419, 0.697, 1.37) + pheomelanin * vec3(0.187, 0.4, 1.05);
+        HairMaterial::new(sigma_a, beta_m, beta_n, alpha)
+    }
+    // absorption giving approximately the color after multiple scattering
+    pub fn colored(color: Vector3, beta_m: f64, beta_n: f64, alpha: f64) -> HairMaterial {
+        let b = beta_n;
+        let d = 5.969 - 0.215 * b + 2.532 * b * b - 10.73 * b.powi(3) + 5.574 * b.powi(4) + 0.245 * b.powi(5);
+        let sigma_a = |c: f64| math::pow2(c.max(1e-4).ln() / d);
+        HairMaterial::new(vec3(sigma_a(color.x), sigma_a(color.y), sigma_a(color.z)), beta_m, beta_n, alpha)
+    }
+    fn fiber(&self, isec: &Intersection) -> HairFiber {
+        HairFiber::new(self.sigma_a.value(isec), self.beta_m.value(isec), self.beta_n.value(isec), self.alpha.value(isec))
+    }
+}
+
+// hair fiber with parameters at intersection
+struct HairFiber {
+    sigma_a: Vector3,
+    eta: f64,
+    // longitudinal variances and azimuthal logistic scale
+    v: [f64; P_MAX + 1],
+    s: f64,
+    // sin and cos of 2^k times scale tilt angle
+    sin_2k_alpha: [f64; 3],
+    cos_2k_alpha: [f64; 3],
+}
+
+impl HairFiber {
+    fn new(sigma_a: Vector3, beta_m: f64, beta_n: f64, alpha: f64) -> HairFiber {
+        let v0 = math::pow2(0.726 * beta_m + 0.812 * beta_m * beta_m + 3.7 * beta_m.powi(20));
+        let v = [v0, 0.25 * v0, 4.0 * v0, 4.0 * v0];
+        let s = (math::PI / 8.0).sqrt() * (0.265 * beta_n + 1.194 * beta_n * beta_n + 5.372 * beta_n.powi(22));
+        let mut sin_2k_alpha = [alpha.to_radians().sin(), 0.0, 0.0];
+        let mut cos_2k_alpha = [(1.0 - sin_2k_alpha[0] * sin_2k_alpha[0]).max(0.0).sqrt(), 0.0, 0.0];
+        for i in 1..3 {
+            sin_2k_alpha[i] = 2.0 * cos_2k_alpha[i - 1] * sin_2k_alpha[i - 1];
+            cos_2k_alpha[i] = math::pow2(cos_2k_alpha[i - 1]) - math::pow2(sin_2k_alpha[i - 1]);
+        }
+        HairFiber { sigma_a, eta: 1.55, v, s, sin_2k_alpha, cos_2k_alpha }
+    }
+    // sin and cos of theta_o rotated by tilt of scales for each lobe
+    fn tilt(&self, p: usize, sin_o: f64, cos_o: f64) -> (f64, f64) {
+        let (s, c) = (&self.sin_2k_alpha, &self.cos_2k_alpha);
+        let (sin_op, cos_op) = match p {
+            0 => (sin_o * c[1] - cos_o * s[1], cos_o * c[1] + sin_o * s[1]),
+            1 => (sin_o * c[0] + cos_o * s[0], cos_o * c[0] - sin_o * s[0]),
+            2 => (sin_o * c[2] + cos_o * s[2], cos_o * c[2] - sin_o * s[2]),
+            _ => (sin_o, cos_o),
+        };
+        (sin_op, cos_op.abs())
+    }
+    // attenuation of each lobe, and angle of refracted ray in azimuthal plane
+    fn attenuation(&self, sin_o: f64, cos_o: f64, h: f64) -> ([Vector3; P_MAX + 1], f64) {
+        let sin_t = sin_o / self.eta;
+        let cos_t = (1.0 - sin_t * sin_t).max(0.0).sqrt();
+        // modified index of refraction for projected directions
+        let etap = (self.eta * self.eta - sin_o * sin_o).sqrt() / cos_o;
+        let sin_gamma_t = h / etap;
+        let cos_gamma_t = (1.0 - sin_gamma_t * sin_gamma_t).max(0.0).sqrt();
+        let gamma_t = math::clamp(sin_gamma_t, -1.0, 1.0).asin();
+        let d = 2.0 * cos_gamma_t / cos_t;
+        let t = vec3((-self.sigma_a.x * d).exp(), (-self.sigma_a.y * d).exp(), (-self.sigma_a.z * d).exp());
+
+        let cos_gamma_o = (1.0 - h * h).max(0.0).sqrt();
+        let f = math::fresnel_dielectric(cos_o * cos_gamma_o, self.eta);
+        let mut ap = [Vector3::zero(); P_MAX + 1];
+        ap[0] = Vector3::one() * f;
+        ap[1] = math::pow2(1.0 - f) * t;
+        for p in 2..P_MAX {
+            ap[p] = ap[p - 1] * t * f;
+        }
+        ap[P_MAX] = ap[P_MAX - 1] * f * t / (1.0 - t * f);
+        (ap, gamma_t)
+    }
+    fn lobe_probabilities(&self, ap: &[Vector3; P_MAX + 1]) -> [f64; P_MAX + 1] {
+        let sum: f64 = ap.iter().map(|a| color::luminance(*a)).sum();
+        let mut probabilities = [0.0; P_MAX + 1];
+        for p in 0..=P_MAX {
+            probabilities[p] = if sum > 0.0 { color::luminance(ap[p]) / sum } else { 0.0 };
+        }
+        probabilities
+    }
+    // offset across fiber, and sin, cos and azimuth of wo in local space
+    fn geometry(&self, isec: &Intersection) -> (f64, f64, f64, f64) {
+        let h = math::clamp(-1.0 + 2.0 * isec.uv.1, -1.0, 1.0);
+        let wo = isec.to_local(isec.wo);
+        let sin_o = math::clamp(wo.x, -1.0, 1.0);
+        (h, sin_o, (1.0 - sin_o * sin_o).max(0.0).sqrt(), wo.z.atan2(wo.y))
+    }
+    // sum of lobes weighted by attenuation or by probabilities of lobes
+    fn lobes<T: Copy + std::ops::Mul<f64, Output = T> + std::ops::Add<Output = T>>(
+        &self, weights: &[T; P_MAX + 1], zero: T, h: f64, gamma_t: f64, o: (f64, f64, f64), wi: Vector3) -> T
+    {
+        let (sin_o, cos_o, phi_o) = o;
+        let sin_i = math::clamp(wi.x, -1.0, 1.0);
+        let cos_i = (1.0 - sin_i * sin_i).max(0.0).sqrt();
+        let phi = wi.z.atan2(wi.y) - phi_o;
+        let gamma_o = h.asin();
+        let mut sum = zero;
+        for (p, weight) in weights.iter().enumerate().take(P_MAX) {
+            let (sin_op, cos_op) = self.tilt(p, sin_o, cos_o);
+            let m = longitudinal(cos_i, cos_op, sin_i, sin_op, self.v[p]);
+            let n = azimuthal(phi, p, self.s, gamma_o, gamma_t);
+            sum = sum + *weight * (m * n);
+        }
+        let m = longitudinal(cos_i, cos_o, sin_i, sin_o, self.v[P_MAX]);
+        sum + weights[P_MAX] * (m / math::TWO_PI)
+    }
+}
+
+impl Material for HairMaterial {
+    fn bsdf(&self, isec: &Intersection, wi: Vector3) -> Vector3 {
+        self.fiber(isec).bsdf(isec, wi)
+    }
+    fn sample(&self, isec: &Intersection) -> Option<Bsdf> {
+        self.fiber(isec).sample(isec)
+    }
+    fn pdf(&self, isec: &Intersection, wi: Vector3) -> f64 {
+        self.fiber(isec).pdf(isec, wi)
+    }
+}
+
+impl HairFiber {
+    fn bsdf(&self, isec: &Intersection, wi: Vector3) -> Vector3 {
+        let (h, sin_o, cos_o, phi_o) = self.geometry(isec);
+        let (ap, gamma_t) = self.attenuation(sin_o, cos_o, h);
+        let wi = isec.to_local(wi);
+        let value = self.lobes(&ap, Vector3::zero(), h, gamma_t, (sin_o, cos_o, phi_o), wi);
+        // cosine factor multiplied by integrator is canceled
+        if wi.z != 0.0 { value / wi.z.abs() } else { value }
+    }
+    fn sample(&self, isec: &Intersection) -> Option<Bsdf> {
+        let (h, sin_o, cos_o, phi_o) = self.geometry(isec);
+        let (ap, gamma_t) = self.attenuation(sin_o, cos_o, h);
+        let probabilities = self.lobe_probabilities(&ap);
+
+        // choose lobe
+        let mut r = math::random();
+        let mut p = 0;
+        while p < P_MAX && r >= probabilities[p] {
+            r -= probabilities[p];
+            p += 1;
+        }
+
+        // sample longitudinal angle
+        let (sin_op, cos_op) = self.tilt(p, sin_o, cos_o);
+        let u = math::random().max(1e-5);
+        let v = self.v[p];
+        let cos_theta = 1.0 + v * (u + (1.0 - u) * (-2.0 / v).exp()).ln();
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let cos_phi = (math::TWO_PI * math::random()).cos();
+        let sin_i = math::clamp(-cos_theta * sin_op + sin_theta * cos_phi * cos_op, -1.0, 1.0);
+        let cos_i = (1.0 - sin_i * sin_i).max(0.0).sqrt();
+
+        // sample azimuthal angle
+        let dphi = if p < P_MAX {
+            phi_lobe(p, h.asin(), gamma_t) + sample_trimmed_logistic(math::random(), self.s, -math::PI, math::PI)
+        } else {
+            math::TWO_PI * math::random()
+        };
+        let phi_i = phi_o + dphi;
+        let wi = isec.to_world(vec3(sin_i, cos_i * phi_i.cos(), cos_i * phi_i.sin()));
+
+        let pdf = self.pdf(isec, wi);
+        if pdf == 0.0 {
+            return None;
+        }
+        let value = self.bsdf(isec, wi);
+        Some(Bsdf { value, wi, pdf, specular: false })
+    }
+    fn pdf(&self, isec: &Intersection, wi: Vector3) -> f64 {
+        let (h, sin_o, cos_o, phi_o) = self.geometry(isec);
+        let (ap, gamma_t) = self.attenuation(sin_o, cos_o, h);
+        let probabilities = self.lobe_probabilities(&ap);
+        self.lobes(&probabilities, 0.0, h, gamma_t, (sin_o, cos_o, phi_o), isec.to_local(wi))
+    }
+}
+
+// longitudinal scattering function
+fn longitudinal(cos_i: f64, cos_o: f64, sin_i: f64, sin_o: f64, v: f64) -> f64 {
+    let a = cos_i * cos_o / v;
+    let b = sin_i * sin_o / v;
+    if v <= 0.1 {
+        (log_bessel_i0(a) - b - 1.0 / v + std::f64::consts::LN_2 + (1.0 / (2.0 * v)).ln()).exp()
+    } else {
+        (-b).exp() * bessel_i0(a) / ((1.0 / v).sinh() * 2.0 * v)
+    }
+}
+
+// modified bessel function of the first kind
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 0.0;
+    let mut term = 1.0;
+    let x2 = x * x;
+    // series is summed until convergence since few terms are not enough for large x
+    for i in 1..100 {
+        sum += term;
+        term *= x2 / (4.0 * (i * i) as f64);
+        if term < 1e-16 * sum {
+            break;
+        }
+    }
+    sum
+}
+
+fn log_bessel_i0(x: f64) -> f64 {
+    if x > 12.0 {
+        x - 0.5 * (math::TWO_PI * x).ln() + (1.0 + 1.0 / (8.0 * x)).ln()
+    } else {
+        bessel_i0(x).ln()
+    }
+}
+
+// azimuthal angle of exiting direction of lobe p
+fn phi_lobe(p: usize, gamma_o: f64, gamma_t: f64) -> f64 {
+    let p = p as f64;
+    2.0 * p * gamma_t - 2.0 * gamma_o + p * math::PI
+}
+
+// azimuthal scattering function
+fn azimuthal(phi: f64, p: usize, s: f64, gamma_o: f64, gamma_t: f64) -> f64 {
+    let mut dphi = phi - phi_lobe(p, gamma_o, gamma_t);
+    while dphi > math::PI {
+        dphi -= math::TWO_PI;
+    }
+    while dphi < -math::PI {
+        dphi += math::TWO_PI;
+    }
+    trimmed_logistic(dphi, s, -math::PI, math::PI)
+}
+
+fn logistic(x: f64, s: f64) -> f64 {
+    let e = (-x.abs() / s).exp();
+    e / (s * math::pow2(1.0 + e))
+}
+
+fn logistic_cdf(x: f64, s: f64) -> f64 {
+    1.0 / (1.0 + (-x / s).exp())
+}
+
+fn trimmed_logistic(x: f64, s: f64, a: f64, b: f64) -> f64 {
+    logistic(x, s) / (logistic_cdf(b, s) - logistic_cdf(a, s))
+}
+
+fn sample_trimmed_logistic(u: f64, s: f64, a: f64, b: f64) -> f64 {
+    let k = logistic_cdf(b, s) - logistic_cdf(a, s);
+    let x = -s * (1.0 / (u * k + logistic_cdf(a, s)) - 1.0).ln();
+    math::clamp(x, a, b)
+}
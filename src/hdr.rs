@@ -0,0 +1,516 @@
+use std::path::Path;
+
+use crate::{ vec3, Vector3 };
+
+// image of linear floating point values whose rows are stored from top to bottom
+pub struct HdrImage {
+    width: usize,
+    height: usize,
+    pixels: Vec<Vector3>,
+}
+
+impl HdrImage {
+    pub fn new(width: usize, height: usize) -> HdrImage {
+        HdrImage { width, height, pixels: vec![Vector3::zero(); width * height] }
+    }
+    pub fn width(&self) -> usize {
+        self.width
+    }
+    pub fn height(&self) -> usize {
+        self.height
+    }
+    pub fn pixels(&self) -> &[Vector3] {
+        &self.pixels
+    }
+    pub fn get(&self, x: usize, y: usize) -> Vector3 {
+        self.pixels[y * self.width + x]
+    }
+    pub fn set(&mut self, x: usize, y: usize, value: Vector3) {
+        self.pixels[y * self.width + x] = value;
+    }
+
+    // whether format of the file is supported by extension
+    pub fn is_supported(filename: &str) -> bool {
+        matches!(extension(filename).as_str(), "hdr" | "pfm" | "exr")
+    }
+    // format is chosen by extension of .hdr, .pfm or .exr
+    pub fn load(filename: &str) -> HdrImage {
+        let bytes = std::fs::read(filename).unwrap();
+        let image = match extension(filename).as_str() {
+            "hdr" => HdrImage::decode_rgbe(&bytes),
+            "pfm" => HdrImage::decode_pfm(&bytes),
+            "exr" => HdrImage::decode_exr(&bytes),
+            _ => None,
+        };
+        image.expect("unsupported HDR image file")
+    }
+    // floating point image, or 8-bit image converted to linear values.
+    // decode maps 8-bit values scaled to [0, 1] to linear ones, floating point images are always linear
+    pub fn open(filename: &str, decode: fn(f64) -> f64) -> HdrImage {
+        if HdrImage::is_supported(filename) {
+            return HdrImage::load(filename);
+        }
+        let image = image::open(filename).unwrap().to_rgb();
+        let value = |v: u8| decode(v as f64 / 255.0);
+        let pixels = image.pixels().map(|p| vec3(value(p[0]), value(p[1]), value(p[2]))).collect();
+        HdrImage { width: image.width() as usize, height: image.height() as usize, pixels }
+    }
+    pub fn save(&self, filename: &str) {
+        let bytes = match extension(filename).as_str() {
+            "hdr" => self.encode_rgbe(),
+            "pfm" => self.encode_pfm(),
+            "exr" => self.encode_exr(),
+            _ => panic!("unsupported HDR image file"),
+        };
+        std::fs::write(filename, bytes).unwrap();
+    }
+
+    // radiance picture of RGBE pixels. uncompressed and new run length encoded scanlines are supported
+    pub fn decode_rgbe(bytes: &[u8]) -> Option<HdrImage> {
+        let mut reader = Reader::new(bytes);
+        if !reader.line()?.starts_with("#?") {
+            return None;
+        }
+        loop {
+            let line = reader.line()?;
+            if line.is_empty() {
+                break;
+            }
+            if line.starts_with("FORMAT=") && line != "FORMAT=32-bit_rle_rgbe" {
+                return None;
+            }
+        }
+        let resolution = reader.line()?;
+        let tokens = resolution.split_whitespace().collect::<Vec<&str>>();
+        if tokens.len() != 4 || tokens[0] != "-Y" || tokens[2] != "+X" {
+            return None;
+        }
+        let height = tokens[1].parse::<usize>().ok()?;
+        let width = tokens[3].parse::<usize>().ok()?;
+
+        let mut image = HdrImage::new(width, height);
+        let mut scanline = vec![0u8; 4 * width];
+        for y in 0..height {
+            let rle = (8..0x8000).contains(&width) && reader.peek(4).is_some_and(|header| {
+                header[0] == 2 && header[1] == 2 && ((header[2] as usize) << 8 | header[3] as usize) == width
+            });
+            if rle {
+                reader.bytes(4)?;
+                // each component is stored separately
+                for c in 0..4 {
+                    let mut x = 0;
+                    while x < width {
+                        let count = reader.u8()? as usize;
+                        if count > 128 {
+                            let count = count - 128;
+                            let value = reader.u8()?;
+                            if x + count > width {
+                                return None;
+                            }
+                            for i in 0..count {
+                                scanline[4 * (x + i) + c] = value;
+                            }
+                            x += count;
+                        } else {
+                            if count == 0 || x + count > width {
+                                return None;
+                            }
+                            for (i, value) in reader.bytes(count)?.iter().enumerate() {
+                                scanline[4 * (x + i) + c] = *value;
+                            }
+                            x += count;
+                        }
+                    }
+                }
+            } else {
+                scanline.copy_from_slice(reader.bytes(4 * width)?);
+            }
+            for x in 0..width {
+                let p = &scanline[4 * x..4 * x + 4];
+                image.set(x, y, rgbe_to_rgb([p[0], p[1], p[2], p[3]]));
+            }
+        }
+        Some(image)
+    }
+    // scanlines are run length encoded when width allows it
+    pub fn encode_rgbe(&self) -> Vec<u8> {
+        let mut bytes = format!("#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n", self.height, self.width).into_bytes();
+        let rle = (8..0x8000).contains(&self.width);
+        for row in self.pixels.chunks(self.width.max(1)) {
+            let scanline = row.iter().map(|pixel| rgb_to_rgbe(*pixel)).collect::<Vec<[u8; 4]>>();
+            if rle {
+                bytes.extend_from_slice(&[2, 2, (self.width >> 8) as u8, (self.width & 0xff) as u8]);
+                for c in 0..4 {
+                    run_length_encode(&scanline.iter().map(|p| p[c]).collect::<Vec<u8>>(), &mut bytes);
+                }
+            } else {
+                for p in &scanline {
+                    bytes.extend_from_slice(p);
+                }
+            }
+        }
+        bytes
+    }
+
+    // portable float map of color (PF) or grayscale (Pf), whose rows are stored from bottom to top
+    pub fn decode_pfm(bytes: &[u8]) -> Option<HdrImage> {
+        let mut reader = Reader::new(bytes);
+        let channels = match reader.token()?.as_str() {
+            "PF" => 3,
+            "Pf" => 1,
+            _ => return None,
+        };
+        let width = reader.token()?.parse::<usize>().ok()?;
+        let height = reader.token()?.parse::<usize>().ok()?;
+        let scale = reader.token()?.parse::<f64>().ok()?;
+        // single whitespace separates header and data
+        reader.u8()?;
+        let little_endian = scale < 0.0;
+
+        let mut image = HdrImage::new(width, height);
+        for y in (0..height).rev() {
+            for x in 0..width {
+                let mut values = [0.0; 3];
+                for value in values.iter_mut().take(channels) {
+                    let b = reader.bytes(4)?;
+                    let b = [b[0], b[1], b[2], b[3]];
+                    let v = if little_endian { f32::from_le_bytes(b) } else { f32::from_be_bytes(b) };
+                    *value = v as f64;
+                }
+                let pixel = if channels == 1 { vec3(values[0], values[0], values[0]) } else { vec3(values[0], values[1], values[2]) };
+                image.set(x, y, pixel);
+            }
+        }
+        Some(image)
+    }
+    pub fn encode_pfm(&self) -> Vec<u8> {
+        let mut bytes = format!("PF\n{} {}\n-1.0\n", self.width, self.height).into_bytes();
+        for y in (0..self.height).rev() {
+            for x in 0..self.width {
+                let pixel = self.get(x, y);
+                for v in &[pixel.x, pixel.y, pixel.z] {
+                    bytes.extend_from_slice(&(*v as f32).to_le_bytes());
+                }
+            }
+        }
+        bytes
+    }
+
+    // single part scanline OpenEXR with channels R, G, B or Y.
+    // supported compressions are none, ZIPS and ZIP
+    pub fn decode_exr(bytes: &[u8]) -> Option<HdrImage> {
+        let mut reader = Reader::new(bytes);
+        if reader.u32()? != EXR_MAGIC {
+            return None;
+        }
+        let version = reader.u32()?;
+        // tiled, deep or multipart image
+        if version & 0xff != 2 || version & 0x1a00 != 0 {
+            return None;
+        }
+
+        let mut channels = Vec::new();
+        let mut compression = None;
+        let mut window = None;
+        loop {
+            let name = reader.cstring()?;
+            if name.is_empty() {
+                break;
+            }
+            let _type = reader.cstring()?;
+            let size = reader.u32()? as usize;
+            let mut value = Reader::new(reader.bytes(size)?);
+            match name.as_str() {
+                "channels" => loop {
+                    let channel = value.cstring()?;
+                    if channel.is_empty() {
+                        break;
+                    }
+                    let pixel_type = value.u32()?;
+                    value.bytes(4)?;
+                    let (x_sampling, y_sampling) = (value.u32()?, value.u32()?);
+                    if pixel_type > 2 || x_sampling != 1 || y_sampling != 1 {
+                        return None;
+                    }
+                    channels.push((channel, pixel_type));
+                },
+                "compression" => compression = Some(value.u8()?),
+                "dataWindow" => window = Some((value.u32()? as i32, value.u32()? as i32, value.u32()? as i32, value.u32()? as i32)),
+                _ => {},
+            }
+        }
+        let (xmin, ymin, xmax, ymax) = window?;
+        // empty window is allowed for image without pixels
+        if xmax < xmin - 1 || ymax < ymin - 1 {
+            return None;
+        }
+        let lines = match compression? {
+            0 | 2 => 1,
+            3 => 16,
+            _ => return None,
+        };
+        let width = (xmax - xmin + 1) as usize;
+        let height = (ymax - ymin + 1) as usize;
+
+        // index of color component each channel is stored in
+        let has_color = channels.iter().any(|(name, _)| name == "R" || name == "G" || name == "B");
+        let targets = channels.iter().map(|(name, _)| match name.as_str() {
+            "R" => vec![0],
+            "G" => vec![1],
+            "B" => vec![2],
+            "Y" if !has_color => vec![0, 1, 2],
+            _ => vec![],
+        }).collect::<Vec<Vec<usize>>>();
+        let sizes = channels.iter().map(|(_, pixel_type)| if *pixel_type == 1 { 2 } else { 4 }).collect::<Vec<usize>>();
+        let line_size = width * sizes.iter().sum::<usize>();
+
+        let chunks = height.div_ceil(lines);
+        let offsets = (0..chunks).map(|_| reader.u64()).collect::<Option<Vec<u64>>>()?;
+        let mut image = HdrImage::new(width, height);
+        for offset in offsets {
+            let mut chunk = Reader::new(bytes.get(offset as usize..)?);
+            let y0 = chunk.u32()? as i32 - ymin;
+            if y0 < 0 || y0 as usize >= height {
+                return None;
+            }
+            let y0 = y0 as usize;
+            let count = lines.min(height - y0);
+            let size = chunk.u32()? as usize;
+            let data = chunk.bytes(size)?;
+            let expected = count * line_size;
+            // data is stored as is if compression does not reduce its size
+            let data = if compression? == 0 || size == expected { data.to_vec() } else { unzip(data)? };
+            if data.len() != expected {
+                return None;
+            }
+            let mut values = Reader::new(&data);
+            for y in y0..(y0 + count) {
+                let mut row = vec![Vector3::zero(); width];
+                for (c, (_, pixel_type)) in channels.iter().enumerate() {
+                    for pixel in row.iter_mut() {
+                        let v = match pixel_type {
+                            0 => values.u32()? as f64,
+                            1 => half_to_f32(values.u16()?) as f64,
+                            _ => f32::from_bits(values.u32()?) as f64,
+                        };
+                        for target in &targets[c] {
+                            pixel[*target] = v;
+                        }
+                    }
+                }
+                for (x, pixel) in row.into_iter().enumerate() {
+                    image.set(x, y, pixel);
+                }
+            }
+        }
+        Some(image)
+    }
+    // float channels with ZIP compression
+    pub fn encode_exr(&self) -> Vec<u8> {
+        let lines = 16;
+        let (w, h) = (self.width as i32, self.height as i32);
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&EXR_MAGIC.to_le_bytes());
+        bytes.extend_from_slice(&2u32.to_le_bytes());
+
+        let mut attribute = |name: &str, ty: &str, value: &[u8]| {
+            bytes.extend_from_slice(name.as_bytes());
+            bytes.push(0);
+            bytes.extend_from_slice(ty.as_bytes());
+            bytes.push(0);
+            bytes.extend_from_slice(&(value.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(value);
+        };
+        // channels are sorted by name
+        let mut channels = Vec::new();
+        for name in &["B", "G", "R"] {
+            channels.extend_from_slice(name.as_bytes());
+            channels.push(0);
+            for v in &[2u32, 0, 1, 1] {
+                channels.extend_from_slice(&v.to_le_bytes());
+            }
+        }
+        channels.push(0);
+        // window of empty image has maximum one less than minimum
+        let window = [0, 0, w - 1, h - 1].iter().flat_map(|v: &i32| v.to_le_bytes().to_vec()).collect::<Vec<u8>>();
+        attribute("channels", "chlist", &channels);
+        attribute("compression", "compression", &[3]);
+        attribute("dataWindow", "box2i", &window);
+        attribute("displayWindow", "box2i", &window);
+        attribute("lineOrder", "lineOrder", &[0]);
+        attribute("pixelAspectRatio", "float", &1.0f32.to_le_bytes());
+        attribute("screenWindowCenter", "v2f", &[0; 8]);
+        attribute("screenWindowWidth", "float", &1.0f32.to_le_bytes());
+        bytes.push(0);
+
+        let chunks = (0..self.height).step_by(lines).map(|y0| {
+            let mut data = Vec::new();
+            for y in y0..(y0 + lines).min(self.height) {
+                for c in &[2, 1, 0] {
+                    for x in 0..self.width {
+                        data.extend_from_slice(&(self.get(x, y)[*c] as f32).to_le_bytes());
+                    }
+                }
+            }
+            let compressed = zip(&data);
+            let data = if compressed.len() < data.len() { compressed } else { data };
+            (y0, data)
+        }).collect::<Vec<(usize, Vec<u8>)>>();
+
+        let mut offset = (bytes.len() + 8 * chunks.len()) as u64;
+        for (_, data) in &chunks {
+            bytes.extend_from_slice(&offset.to_le_bytes());
+            offset += 8 + data.len() as u64;
+        }
+        for (y0, data) in chunks {
+            bytes.extend_from_slice(&(y0 as u32).to_le_bytes());
+            bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(&data);
+        }
+        bytes
+    }
+}
+
+const EXR_MAGIC: u32 = 20000630;
+
+fn extension(filename: &str) -> String {
+    Path::new(filename).extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase()
+}
+
+fn rgbe_to_rgb(rgbe: [u8; 4]) -> Vector3 {
+    if rgbe[3] == 0 {
+        return Vector3::zero();
+    }
+    let f = 2.0f64.powi(rgbe[3] as i32 - (128 + 8));
+    vec3(rgbe[0] as f64 * f, rgbe[1] as f64 * f, rgbe[2] as f64 * f)
+}
+
+// runs of three or more equal bytes are stored as count above 128 and the byte,
+// others are stored as count up to 128 and the literal bytes
+fn run_length_encode(data: &[u8], bytes: &mut Vec<u8>) {
+    let literal = |data: &[u8], bytes: &mut Vec<u8>| {
+        for chunk in data.chunks(128) {
+            bytes.push(chunk.len() as u8);
+            bytes.extend_from_slice(chunk);
+        }
+    };
+    let (mut start, mut x) = (0, 0);
+    while x < data.len() {
+        let mut run = 1;
+        while x + run < data.len() && run < 127 && data[x + run] == data[x] {
+            run += 1;
+        }
+        if run >= 3 {
+            literal(&data[start..x], bytes);
+            bytes.push(128 + run as u8);
+            bytes.push(data[x]);
+            start = x + run;
+        }
+        x += run;
+    }
+    literal(&data[start..], bytes);
+}
+
+fn rgb_to_rgbe(rgb: Vector3) -> [u8; 4] {
+    let v = rgb.x.max(rgb.y).max(rgb.z);
+    if v < 1e-32 {
+        return [0, 0, 0, 0];
+    }
+    // v = mantissa * 2^exponent where mantissa is in [0.5, 1)
+    let mut exponent = v.log2().floor() as i32 + 1;
+    let mantissa = v / 2.0f64.powi(exponent);
+    if mantissa >= 1.0 {
+        exponent += 1;
+    } else if mantissa < 0.5 {
+        exponent -= 1;
+    }
+    let scale = 256.0 / 2.0f64.powi(exponent);
+    let encode = |c: f64| (c.max(0.0) * scale + 0.5).min(255.0) as u8;
+    [encode(rgb.x), encode(rgb.y), encode(rgb.z), (exponent + 128).clamp(0, 255) as u8]
+}
+
+fn half_to_f32(h: u16) -> f32 {
+    let sign = if h & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((h >> 10) & 0x1f) as i32;
+    let mantissa = (h & 0x3ff) as f32;
+    match exponent {
+        0 => sign * mantissa * 2.0f32.powi(-24),
+        31 => if mantissa == 0.0 { sign * f32::INFINITY } else { f32::NAN },
+        _ => sign * (1.0 + mantissa / 1024.0) * 2.0f32.powi(exponent - 15),
+    }
+}
+
+// zlib stream of bytes which are split into halves of even and odd indices and delta encoded
+fn unzip(data: &[u8]) -> Option<Vec<u8>> {
+    let mut t = inflate::inflate_bytes_zlib(data).ok()?;
+    for i in 1..t.len() {
+        t[i] = t[i - 1].wrapping_add(t[i]).wrapping_sub(128);
+    }
+    let half = t.len().div_ceil(2);
+    Some((0..t.len()).map(|i| if i % 2 == 0 { t[i / 2] } else { t[half + i / 2] }).collect())
+}
+
+fn zip(data: &[u8]) -> Vec<u8> {
+    let half = data.len().div_ceil(2);
+    let mut t = vec![0u8; data.len()];
+    for (i, v) in data.iter().enumerate() {
+        t[if i % 2 == 0 { i / 2 } else { half + i / 2 }] = *v;
+    }
+    for i in (1..t.len()).rev() {
+        t[i] = t[i].wrapping_sub(t[i - 1]).wrapping_add(128);
+    }
+    deflate::deflate_bytes_zlib(&t)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Reader<'a> {
+        Reader { bytes, pos: 0 }
+    }
+    fn peek(&self, n: usize) -> Option<&'a [u8]> {
+        self.bytes.get(self.pos..self.pos + n)
+    }
+    fn bytes(&mut self, n: usize) -> Option<&'a [u8]> {
+        let bytes = self.peek(n)?;
+        self.pos += n;
+        Some(bytes)
+    }
+    fn u8(&mut self) -> Option<u8> {
+        self.bytes(1).map(|b| b[0])
+    }
+    fn u16(&mut self) -> Option<u16> {
+        self.bytes(2).map(|b| u16::from_le_bytes([b[0], b[1]]))
+    }
+    fn u32(&mut self) -> Option<u32> {
+        self.bytes(4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+    fn u64(&mut self) -> Option<u64> {
+        self.bytes(8).map(|b| u64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]))
+    }
+    // null terminated string
+    fn cstring(&mut self) -> Option<String> {
+        let len = self.bytes[self.pos..].iter().position(|b| *b == 0)?;
+        let s = String::from_utf8(self.bytes(len)?.to_vec()).ok()?;
+        self.pos += 1;
+        Some(s)
+    }
+    // line terminated by newline
+    fn line(&mut self) -> Option<String> {
+        let len = self.bytes[self.pos..].iter().position(|b| *b == b'\n')?;
+        let s = String::from_utf8(self.bytes(len)?.to_vec()).ok()?;
+        self.pos += 1;
+        Some(s.trim_end_matches('\r').to_string())
+    }
+    // token separated by whitespace
+    fn token(&mut self) -> Option<String> {
+        while self.peek(1)?[0].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+        let len = self.bytes[self.pos..].iter().position(|b| b.is_ascii_whitespace())?;
+        String::from_utf8(self.bytes(len)?.to_vec()).ok()
+    }
+}
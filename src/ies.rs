@@ -0,0 +1,132 @@
+use crate::Vector3;
+
+// photometric data of IES LM-63 file
+// only type C photometry is supported. in local space of luminaire,
+// -y axis is nadir (vertical angle 0) and +x axis is horizontal angle 0.
+pub struct IesProfile {
+    vertical_angles: Vec<f64>,
+    horizontal_angles: Vec<f64>,
+    candela: Vec<Vec<f64>>,
+    max_candela: f64,
+}
+
+impl IesProfile {
+    pub fn parse(text: &str) -> Option<IesProfile> {
+        let mut lines = text.lines();
+        let tilt = lines.by_ref()
+            .map(|line| line.trim())
+            .find(|line| line.starts_with("TILT="))?;
+        let mut values = lines
+            .flat_map(|line| line.split(|c: char| c.is_whitespace() || c == ','))
+            .filter(|s| !s.is_empty())
+            .map(|s| s.parse::<f64>().ok());
+
+        if tilt == "TILT=INCLUDE" {
+            let _geometry = values.next()??;
+            let count = values.next()?? as usize;
+            for _ in 0..(2 * count) {
+                values.next()??;
+            }
+        } else if tilt != "TILT=NONE" {
+            // tilt data in separated file is not supported
+            return None;
+        }
+
+        let mut next = || values.next().flatten();
+        let _lamps = next()?;
+        let _lumens = next()?;
+        let multiplier = next()?;
+        let vertical_num = next()? as usize;
+        let horizontal_num = next()? as usize;
+        let photometric_type = next()? as u32;
+        let _units = next()?;
+        let _width = next()?;
+        let _length = next()?;
+        let _height = next()?;
+        let ballast_factor = next()?;
+        let ballast_lamp_factor = next()?;
+        let _input_watts = next()?;
+
+        if photometric_type != 1 || vertical_num == 0 || horizontal_num == 0 {
+            return None;
+        }
+
+        let vertical_angles = (0..vertical_num).map(|_| next()).collect::<Option<Vec<f64>>>()?;
+        let horizontal_angles = (0..horizontal_num).map(|_| next()).collect::<Option<Vec<f64>>>()?;
+        // horizontal angles must cover one of ranges defined by symmetry of luminaire
+        let range = (horizontal_angles[0], horizontal_angles[horizontal_num - 1]);
+        if ![(0.0, 0.0), (0.0, 90.0), (0.0, 180.0), (0.0, 360.0), (90.0, 270.0)].contains(&range) {
+            return None;
+        }
+        let scale = multiplier * ballast_factor * ballast_lamp_factor;
+        let candela = (0..horizontal_num).map(|_| {
+            (0..vertical_num).map(|_| next().map(|v| v * scale)).collect::<Option<Vec<f64>>>()
+        }).collect::<Option<Vec<Vec<f64>>>>()?;
+
+        let max_candela = candela.iter().flatten().fold(0.0, |res: f64, v| res.max(*v));
+        Some(IesProfile { vertical_angles, horizontal_angles, candela, max_candela })
+    }
+
+    pub fn max_candela(&self) -> f64 {
+        self.max_candela
+    }
+
+    // absolute luminous intensity toward direction in local space of luminaire,
+    // already scaled by candela multiplier and ballast factors
+    pub fn candela(&self, dir: Vector3) -> f64 {
+        let dir = dir.norm();
+        let vertical = (-dir.y).clamp(-1.0, 1.0).acos().to_degrees();
+        let horizontal = self.fold_horizontal_angle(dir.z.atan2(dir.x).to_degrees());
+
+        let (v0, v1, tv) = match find_interval(&self.vertical_angles, vertical) {
+            Some(interval) => interval,
+            None => return 0.0,
+        };
+        let (h0, h1, th) = find_interval(&self.horizontal_angles, horizontal)
+            .unwrap_or_else(|| {
+                let last = self.horizontal_angles.len() - 1;
+                if horizontal < self.horizontal_angles[0] { (0, 0, 0.0) } else { (last, last, 0.0) }
+            });
+
+        let c0 = (1.0 - tv) * self.candela[h0][v0] + tv * self.candela[h0][v1];
+        let c1 = (1.0 - tv) * self.candela[h1][v0] + tv * self.candela[h1][v1];
+        (1.0 - th) * c0 + th * c1
+    }
+
+    fn fold_horizontal_angle(&self, angle: f64) -> f64 {
+        let angle = if angle < 0.0 { angle + 360.0 } else { angle };
+        let first = self.horizontal_angles[0];
+        let last = self.horizontal_angles[self.horizontal_angles.len() - 1];
+        if first == 90.0 {
+            // bilaterally symmetric about 90-270 degree plane
+            if angle < 90.0 { 180.0 - angle } else if angle > 270.0 { 540.0 - angle } else { angle }
+        } else if last == 0.0 {
+            // rotationally symmetric
+            0.0
+        } else if last == 90.0 {
+            // symmetric in each quadrant
+            let angle = if angle > 180.0 { 360.0 - angle } else { angle };
+            if angle > 90.0 { 180.0 - angle } else { angle }
+        } else if last == 180.0 {
+            // bilaterally symmetric about 0-180 degree plane
+            if angle > 180.0 { 360.0 - angle } else { angle }
+        } else {
+            angle
+        }
+    }
+}
+
+// find indices of angles around the value and interpolation factor between them
+fn find_interval(angles: &[f64], value: f64) -> Option<(usize, usize, f64)> {
+    let last = angles.len() - 1;
+    if value < angles[0] || value > angles[last] {
+        return None;
+    }
+    if last == 0 {
+        return Some((0, 0, 0.0));
+    }
+    let i = angles.iter().position(|a| *a > value).unwrap_or(last).max(1);
+    let (a0, a1) = (angles[i - 1], angles[i]);
+    let t = if a1 > a0 { (value - a0) / (a1 - a0) } else { 0.0 };
+    Some((i - 1, i, t))
+}
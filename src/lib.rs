@@ -5,21 +5,35 @@ mod core;
 mod bvh;
 mod shape;
 mod material;
+mod hair;
+mod ies;
+mod light;
+mod texture;
+mod hdr;
 pub mod math;
+pub mod color;
+pub mod noise;
 pub mod util;
 
 pub use self::vector3::Vector3;
 pub use self::matrix4::Matrix4;
 pub use self::transform::Transform;
-pub use self::core::{ Camera, Scene, Ray, Intersection, Primitive, PPrimitive, Geometry, TransformedPrimitive, Aabb };
+pub use self::core::{ Camera, Scene, Ray, RayDifferential, Intersection, SurfaceDifferential, Primitive, PPrimitive, Geometry, Alpha, TransformedPrimitive, Aabb };
 pub use self::bvh::Bvh;
-pub use self::shape::{ Shape, PShape, Sphere, Rect, Triangle };
+pub use self::shape::{ Shape, PShape, Sphere, Rect, Triangle, Curve };
 pub use self::material::{ 
-    Material, PMaterial, LambertMaterial,
+    Material, PMaterial, LambertMaterial, OrenNayarMaterial,
     IlluminantMaterial,
-    SpecularReflectionMaterial, SpecularTransmissionMaterial,
-    MicrofacetReflectionMaterial,
-    FresnelBlendMaterial};
+    SpecularReflectionMaterial, SpecularTransmissionMaterial, ThinDielectricMaterial,
+    MicrofacetReflectionMaterial, MicrofacetTransmissionMaterial,
+    Conductor, ConductorMaterial,
+    PrincipledMaterial, PrincipledParameters, PrincipledTextures, LayeredMaterial,
+    FresnelBlendMaterial, MixMaterial, SheenMaterial, NormalMapMaterial};
+pub use self::hair::HairMaterial;
+pub use self::ies::IesProfile;
+pub use self::hdr::HdrImage;
+pub use self::texture::{ Texture, PTexture, ConstantTexture, CheckerTexture, Noise, NoiseTexture, TriplanarTexture, ImageTexture };
+pub use self::light::{ Light, PLight, LightSample, LightUnit, PowerUnit, PointLight, SpotLight };
 
 pub fn vec3(x: f64, y: f64, z: f64) -> Vector3 {
     Vector3::new(x, y, z)
@@ -0,0 +1,167 @@
+use crate::{ vec3, Vector3 };
+use crate::Transform;
+use crate::IesProfile;
+use crate::{ color, math };
+
+#[derive(Debug, Clone, Copy)]
+pub enum LightUnit {
+    Watts(f64),
+    Lumens(f64),
+    Nits(f64),
+}
+
+impl LightUnit {
+    // radiance of lambertian emitter with the area
+    pub fn radiance(&self, area: f64) -> f64 {
+        match *self {
+            LightUnit::Watts(watts) => watts / (math::PI * area),
+            LightUnit::Lumens(lumens) => lumens / (color::LUMINOUS_EFFICACY * math::PI * area),
+            LightUnit::Nits(nits) => nits / color::LUMINOUS_EFFICACY,
+        }
+    }
+}
+
+// total power of light without area, for which luminance in nits is not defined
+#[derive(Debug, Clone, Copy)]
+pub enum PowerUnit {
+    Watts(f64),
+    Lumens(f64),
+}
+
+impl PowerUnit {
+    // radiant intensity of light emitting uniformly into the solid angle
+    pub fn intensity(&self, solid_angle: f64) -> f64 {
+        match *self {
+            PowerUnit::Watts(watts) => watts / solid_angle,
+            PowerUnit::Lumens(lumens) => lumens / (color::LUMINOUS_EFFICACY * solid_angle),
+        }
+    }
+}
+
+pub struct LightSample {
+    pub value: Vector3,
+    pub wi: Vector3,
+    pub dist: f64,
+    pub pdf: f64,
+}
+
+pub type PLight = dyn Light + Sync + Send + 'static;
+
+pub trait Light {
+    fn sample(&self, pos: Vector3) -> Option<LightSample>;
+}
+
+// light is placed at origin in local space and emits toward -y axis
+struct LocalLight {
+    pos: Vector3,
+    inv_transform: Transform,
+    profile: Option<IesProfile>,
+}
+
+impl LocalLight {
+    fn new(transform: Transform, profile: Option<IesProfile>) -> LocalLight {
+        let pos = transform.point(Vector3::zero());
+        let inv_transform = transform.inverse();
+        LocalLight { pos, inv_transform, profile }
+    }
+    fn profile_scale(&self, dir: Vector3) -> f64 {
+        self.profile.as_ref().map_or(1.0, |profile| {
+            if profile.max_candela() > 0.0 {
+                profile.candela(dir) / profile.max_candela()
+            } else {
+                0.0
+            }
+        })
+    }
+    // returns sample with unit intensity and emitting direction in local space
+    fn sample(&self, pos: Vector3) -> Option<(LightSample, Vector3)> {
+        let d = self.pos - pos;
+        let dist = d.mag();
+        if dist == 0.0 {
+            return None;
+        }
+        let wi = d / dist;
+        let value = vec3(1.0, 1.0, 1.0) / (dist * dist);
+        let dir = self.inv_transform.vector(-wi).norm();
+        Some((LightSample { value, wi, dist, pdf: 1.0 }, dir))
+    }
+}
+
+pub struct PointLight {
+    intensity: Vector3,
+    light: LocalLight,
+}
+
+impl PointLight {
+    pub fn new(intensity: Vector3, transform: Transform) -> PointLight {
+        PointLight { intensity, light: LocalLight::new(transform, None) }
+    }
+    // only angular shape of profile is used, so intensity is emitted toward its peak
+    // and absolute candela of the file are ignored
+    pub fn with_profile(intensity: Vector3, profile: IesProfile, transform: Transform) -> PointLight {
+        PointLight { intensity, light: LocalLight::new(transform, Some(profile)) }
+    }
+    // color is normalized to unit luminance
+    pub fn with_unit(color: Vector3, unit: PowerUnit, transform: Transform) -> PointLight {
+        let intensity = color::normalize_luminance(color) * unit.intensity(4.0 * math::PI);
+        PointLight::new(intensity, transform)
+    }
+}
+
+impl Light for PointLight {
+    fn sample(&self, pos: Vector3) -> Option<LightSample> {
+        self.light.sample(pos).map(|(sample, dir)| {
+            let scale = self.light.profile_scale(dir);
+            LightSample { value: sample.value * self.intensity * scale, ..sample }
+        })
+    }
+}
+
+pub struct SpotLight {
+    intensity: Vector3,
+    cos_total: f64,
+    cos_falloff: f64,
+    light: LocalLight,
+}
+
+impl SpotLight {
+    pub fn new(intensity: Vector3, total_angle: f64, falloff_angle: f64, transform: Transform) -> SpotLight {
+        SpotLight::create(intensity, total_angle, falloff_angle, transform, None)
+    }
+    // profile is normalized to its peak like the one of PointLight::with_profile
+    pub fn with_profile(intensity: Vector3, total_angle: f64, falloff_angle: f64, profile: IesProfile, transform: Transform) -> SpotLight {
+        SpotLight::create(intensity, total_angle, falloff_angle, transform, Some(profile))
+    }
+    // color is normalized to unit luminance
+    pub fn with_unit(color: Vector3, unit: PowerUnit, total_angle: f64, falloff_angle: f64, transform: Transform) -> SpotLight {
+        // approximate solid angle of the cone with smooth falloff
+        let cosine = 0.5 * (total_angle.to_radians().cos() + falloff_angle.min(total_angle).to_radians().cos());
+        let intensity = color::normalize_luminance(color) * unit.intensity(2.0 * math::PI * (1.0 - cosine));
+        SpotLight::new(intensity, total_angle, falloff_angle, transform)
+    }
+    fn create(intensity: Vector3, total_angle: f64, falloff_angle: f64, transform: Transform, profile: Option<IesProfile>) -> SpotLight {
+        let cos_total = total_angle.to_radians().cos();
+        let cos_falloff = falloff_angle.min(total_angle).to_radians().cos();
+        SpotLight { intensity, cos_total, cos_falloff, light: LocalLight::new(transform, profile) }
+    }
+    fn falloff(&self, dir: Vector3) -> f64 {
+        let cosine = -dir.y;
+        if cosine <= self.cos_total {
+            0.0
+        } else if cosine >= self.cos_falloff {
+            1.0
+        } else {
+            let t = (cosine - self.cos_total) / (self.cos_falloff - self.cos_total);
+            t * t * (3.0 - 2.0 * t)
+        }
+    }
+}
+
+impl Light for SpotLight {
+    fn sample(&self, pos: Vector3) -> Option<LightSample> {
+        self.light.sample(pos).map(|(sample, dir)| {
+            let scale = self.falloff(dir) * self.light.profile_scale(dir);
+            LightSample { value: sample.value * self.intensity * scale, ..sample }
+        })
+    }
+}
@@ -1,11 +1,18 @@
+use std::sync::{ Arc, OnceLock };
+
 use crate::{ vec3, Vector3 };
 use crate::Intersection;
 use crate::math;
+use crate::PTexture;
+use crate::texture::constant;
+use crate::{ color, LightUnit };
 
 pub struct Bsdf {
     pub value: Vector3,
     pub wi: Vector3,
     pub pdf: f64,
+    // sampled from delta distribution, which pdf of the material can not evaluate
+    pub specular: bool,
 }
 
 pub type PMaterial = dyn Material + Sync + Send + 'static;
@@ -13,39 +20,123 @@ pub type PMaterial = dyn Material + Sync + Send + 'static;
 pub trait Material {
     fn bsdf(&self, isec: &Intersection, wi: Vector3) -> Vector3;
     fn sample(&self, isec: &Intersection) -> Option<Bsdf>;
+    // delta distributions can not be evaluated, so materials which only sample them keep zero
+    fn pdf(&self, _isec: &Intersection, _wi: Vector3) -> f64 {
+        0.0
+    }
+    // bsdf() and pdf() are estimates, so value and pdf of a sample are only valid as a pair
+    // and must not be replaced by evaluating bsdf() or pdf() toward the sampled direction
+    fn stochastic(&self) -> bool {
+        false
+    }
     fn emit(&self, isec: &Intersection) -> Vector3 {
         vec3(0.0, 0.0, 0.0)
     }
+    // intersection with shading frame perturbed by the material, which Scene::hit applies at the closest hit.
+    // materials composed of other materials apply it to each of them
+    fn shade(&self, isec: &Intersection) -> Intersection {
+        Intersection { ..*isec }
+    }
 }
 
 pub struct LambertMaterial {
-    reflectance: Vector3,
+    reflectance: Arc<PTexture<Vector3>>,
 }
 
 impl LambertMaterial {
     pub fn new(reflectance: Vector3) -> LambertMaterial {
+        LambertMaterial::textured(constant(reflectance))
+    }
+    pub fn textured(reflectance: Arc<PTexture<Vector3>>) -> LambertMaterial {
         LambertMaterial { reflectance }
     }
 }
 
 impl Material for LambertMaterial {
-    fn bsdf(&self, _isec: &Intersection, _wi: Vector3) -> Vector3 {
-        self.reflectance / math::PI
+    fn bsdf(&self, isec: &Intersection, _wi: Vector3) -> Vector3 {
+        self.reflectance.value(isec) / math::PI
     }
     fn sample(&self, isec: &Intersection) -> Option<Bsdf> {
         let (dir, pdf) = math::sample_random_cosine_dir();
-        let wi = math::change_basis(dir, isec.normal);
+        let wi = isec.to_world(dir);
         let value = self.bsdf(isec, wi);
-        Some(Bsdf { value, wi, pdf })
+        Some(Bsdf { value, wi, pdf, specular: false })
+    }
+    fn pdf(&self, isec: &Intersection, wi: Vector3) -> f64 {
+        isec.normal.dot(wi).max(0.0) / math::PI
+    }
+}
+
+// rough diffuse surface of Oren-Nayar model. sigma is standard deviation of facet angles in degrees.
+pub struct OrenNayarMaterial {
+    reflectance: Arc<PTexture<Vector3>>,
+    sigma: Arc<PTexture<f64>>,
+}
+
+impl OrenNayarMaterial {
+    pub fn new(reflectance: Vector3, sigma: f64) -> OrenNayarMaterial {
+        OrenNayarMaterial::textured(constant(reflectance), constant(sigma))
+    }
+    pub fn textured(reflectance: Arc<PTexture<Vector3>>, sigma: Arc<PTexture<f64>>) -> OrenNayarMaterial {
+        OrenNayarMaterial { reflectance, sigma }
+    }
+    fn coefficients(&self, isec: &Intersection) -> (f64, f64) {
+        let sigma2 = math::pow2(self.sigma.value(isec).to_radians());
+        let a = 1.0 - sigma2 / (2.0 * (sigma2 + 0.33));
+        let b = 0.45 * sigma2 / (sigma2 + 0.09);
+        (a, b)
+    }
+}
+
+impl Material for OrenNayarMaterial {
+    fn bsdf(&self, isec: &Intersection, wi: Vector3) -> Vector3 {
+        let wo = isec.to_local(isec.wo);
+        let wi = isec.to_local(wi);
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return Vector3::zero();
+        }
+        let sin_o = (1.0 - wo.z * wo.z).max(0.0).sqrt();
+        let sin_i = (1.0 - wi.z * wi.z).max(0.0).sqrt();
+        // cosine of difference of azimuth angles
+        let cos_phi = if sin_o > 1e-4 && sin_i > 1e-4 {
+            ((wo.x * wi.x + wo.y * wi.y) / (sin_o * sin_i)).max(0.0)
+        } else {
+            0.0
+        };
+        let (sin_alpha, tan_beta) = if wi.z < wo.z {
+            (sin_i, sin_o / wo.z)
+        } else {
+            (sin_o, sin_i / wi.z)
+        };
+        let (a, b) = self.coefficients(isec);
+        self.reflectance.value(isec) / math::PI * (a + b * cos_phi * sin_alpha * tan_beta)
+    }
+    fn sample(&self, isec: &Intersection) -> Option<Bsdf> {
+        if isec.normal.dot(isec.wo) <= 0.0 {
+            return None;
+        }
+        let (dir, pdf) = math::sample_random_cosine_dir();
+        let wi = isec.to_world(dir);
+        let value = self.bsdf(isec, wi);
+        Some(Bsdf { value, wi, pdf, specular: false })
+    }
+    fn pdf(&self, isec: &Intersection, wi: Vector3) -> f64 {
+        if isec.normal.dot(isec.wo) <= 0.0 {
+            return 0.0;
+        }
+        isec.normal.dot(wi).max(0.0) / math::PI
     }
 }
 
 pub struct SpecularReflectionMaterial {
-    reflectance: Vector3,
+    reflectance: Arc<PTexture<Vector3>>,
 }
 
 impl SpecularReflectionMaterial {
     pub fn new(reflectance: Vector3) -> SpecularReflectionMaterial {
+        SpecularReflectionMaterial::textured(constant(reflectance))
+    }
+    pub fn textured(reflectance: Arc<PTexture<Vector3>>) -> SpecularReflectionMaterial {
         SpecularReflectionMaterial { reflectance }
     }
 }
@@ -56,19 +147,22 @@ impl Material for SpecularReflectionMaterial {
     }
     fn sample(&self, isec: &Intersection) -> Option<Bsdf> {
         let wi = math::reflect(-isec.wo, isec.normal);
-        let value = self.reflectance / isec.normal.dot(wi).max(0.0);
+        let value = self.reflectance.value(isec) / isec.normal.dot(wi).max(0.0);
         let pdf = 1.0;
-        Some(Bsdf{ value, wi, pdf })
+        Some(Bsdf { value, wi, pdf, specular: true })
     }
 }
 
 pub struct SpecularTransmissionMaterial {
-    transmittance: Vector3,
-    ri: f64,
+    transmittance: Arc<PTexture<Vector3>>,
+    ri: Arc<PTexture<f64>>,
 }
 
 impl SpecularTransmissionMaterial {
     pub fn new(transmittance: Vector3, ri: f64) -> SpecularTransmissionMaterial {
+        SpecularTransmissionMaterial::textured(constant(transmittance), constant(ri))
+    }
+    pub fn textured(transmittance: Arc<PTexture<Vector3>>, ri: Arc<PTexture<f64>>) -> SpecularTransmissionMaterial {
         SpecularTransmissionMaterial { transmittance, ri }
     }
 }
@@ -80,182 +174,1613 @@ impl Material for SpecularTransmissionMaterial {
     fn sample(&self, isec: &Intersection) -> Option<Bsdf> {
         let reflect = math::reflect(-isec.wo, isec.normal);
         let dot = isec.normal.dot(isec.wo);
+        let transmittance = self.transmittance.value(isec);
         let (ri, normal, cosine) = if dot > 0.0 {
-            (1.0 / self.ri, isec.normal, dot)
+            (1.0 / self.ri.value(isec), isec.normal, dot)
         } else {
-            (self.ri, -isec.normal, -dot)
+            (self.ri.value(isec), -isec.normal, -dot)
         };
 
         math::refract(isec.wo, normal, ri).map_or(Some(Bsdf {
-            value: self.transmittance / cosine,
+            value: transmittance / cosine,
             wi: reflect,
             pdf: 1.0,
+            specular: true,
         }), |refract| {
             let fresnel = math::schlick_fresnel(cosine, ri);
-            let r = rand::random::<f64>();
+            let r = math::random();
             if r < fresnel {
                 Some(Bsdf {
-                    value: fresnel * self.transmittance / cosine,
+                    value: fresnel * transmittance / cosine,
                     wi: reflect,
                     pdf: fresnel,
+                    specular: true,
                 })
             } else {
                 Some(Bsdf {
-                    value: (1.0 - fresnel) * self.transmittance / cosine,
+                    value: (1.0 - fresnel) * transmittance / cosine,
                     wi: refract,
                     pdf: 1.0 - fresnel,
+                    specular: true,
                 })
             }
         })
     }
 }
 
-pub struct MicrofacetReflectionMaterial {
-    reflectance: Vector3,
-    roughness: f64,
+// infinitely thin sheet of dielectric like window glass or soap bubble.
+// light transmits straight through and internal reflections are summed up analytically.
+pub struct ThinDielectricMaterial {
+    transmittance: Arc<PTexture<Vector3>>,
+    ri: Arc<PTexture<f64>>,
+}
 
+impl ThinDielectricMaterial {
+    pub fn new(transmittance: Vector3, ri: f64) -> ThinDielectricMaterial {
+        ThinDielectricMaterial::textured(constant(transmittance), constant(ri))
+    }
+    pub fn textured(transmittance: Arc<PTexture<Vector3>>, ri: Arc<PTexture<f64>>) -> ThinDielectricMaterial {
+        ThinDielectricMaterial { transmittance, ri }
+    }
+}
+
+impl Material for ThinDielectricMaterial {
+    fn bsdf(&self, _isec: &Intersection, _wi: Vector3) -> Vector3 {
+        Vector3::zero()
+    }
+    fn sample(&self, isec: &Intersection) -> Option<Bsdf> {
+        let cosine = isec.normal.dot(isec.wo).abs();
+        if cosine == 0.0 {
+            return None;
+        }
+        let f = math::fresnel_dielectric(cosine, self.ri.value(isec));
+        // geometric series of light bouncing between both interfaces
+        let reflectance = if f < 1.0 { f + math::pow2(1.0 - f) * f / (1.0 - f * f) } else { 1.0 };
+        if math::random() < reflectance {
+            let wi = math::reflect(-isec.wo, isec.normal);
+            Some(Bsdf { value: Vector3::one() * reflectance / cosine, wi, pdf: reflectance, specular: true })
+        } else {
+            let transmittance = 1.0 - reflectance;
+            Some(Bsdf { value: self.transmittance.value(isec) * transmittance / cosine, wi: -isec.wo, pdf: transmittance, specular: true })
+        }
+    }
+}
+
+pub struct MicrofacetReflectionMaterial {
+    reflectance: Arc<PTexture<Vector3>>,
+    roughness: (Arc<PTexture<f64>>, Arc<PTexture<f64>>),
 }
 
 impl MicrofacetReflectionMaterial {
     pub fn new(reflectance: Vector3, roughness: f64) -> MicrofacetReflectionMaterial {
-        MicrofacetReflectionMaterial { reflectance, roughness }
+        MicrofacetReflectionMaterial::anisotropic(reflectance, roughness, roughness)
+    }
+    // roughness along tangent and bitangent of intersection
+    pub fn anisotropic(reflectance: Vector3, roughness_u: f64, roughness_v: f64) -> MicrofacetReflectionMaterial {
+        MicrofacetReflectionMaterial::textured(constant(reflectance), constant(roughness_u), constant(roughness_v))
+    }
+    pub fn textured(reflectance: Arc<PTexture<Vector3>>, roughness_u: Arc<PTexture<f64>>, roughness_v: Arc<PTexture<f64>>) -> MicrofacetReflectionMaterial {
+        MicrofacetReflectionMaterial { reflectance, roughness: (roughness_u, roughness_v) }
+    }
+    fn roughness(&self, isec: &Intersection) -> (f64, f64) {
+        let roughness = roughness_at(&self.roughness, isec);
+        (roughness.0.max(1e-3), roughness.1.max(1e-3))
+    }
+}
+
+fn roughness_at(roughness: &(Arc<PTexture<f64>>, Arc<PTexture<f64>>), isec: &Intersection) -> (f64, f64) {
+    (roughness.0.value(isec), roughness.1.value(isec))
+}
+
+// microfacet functions below are evaluated in local space whose z axis is normal.
+// roughness is given along x and y axes.
+
+fn ggx_distribution(wh: Vector3, roughness: (f64, f64)) -> f64 {
+    if wh.z <= 0.0 {
+        return 0.0;
+    }
+    let (ax, ay) = roughness;
+    let e = math::pow2(wh.x / ax) + math::pow2(wh.y / ay) + math::pow2(wh.z);
+    1.0 / (math::PI * ax * ay * e * e)
+}
+
+fn ggx_g(wo: Vector3, wi: Vector3, roughness: (f64, f64)) -> f64 {
+    1.0 / (1.0 + ggx_lambda(wo, roughness) + ggx_lambda(wi, roughness))
+}
+
+fn ggx_g1(v: Vector3, roughness: (f64, f64)) -> f64 {
+    1.0 / (1.0 + ggx_lambda(v, roughness))
+}
+
+fn ggx_lambda(v: Vector3, roughness: (f64, f64)) -> f64 {
+    let (ax, ay) = roughness;
+    let tan2 = (math::pow2(ax * v.x) + math::pow2(ay * v.y)) / math::pow2(v.z);
+    0.5 * (-1.0 + (1.0 + tan2).sqrt())
+}
+
+// sample microfacet normal from distribution of visible normals by Heitz
+fn ggx_sample_visible_normal(wo: Vector3, roughness: (f64, f64)) -> Vector3 {
+    ggx_visible_normal(wo, roughness, math::random(), math::random())
+}
+
+fn ggx_visible_normal(wo: Vector3, roughness: (f64, f64), u1: f64, u2: f64) -> Vector3 {
+    let (ax, ay) = roughness;
+    let vh = vec3(ax * wo.x, ay * wo.y, wo.z).norm();
+    let len2 = math::pow2(vh.x) + math::pow2(vh.y);
+    let t1 = if len2 > 0.0 { vec3(-vh.y, vh.x, 0.0) / len2.sqrt() } else { vec3(1.0, 0.0, 0.0) };
+    let t2 = vh.cross(t1);
+    let r = u1.sqrt();
+    let phi = math::TWO_PI * u2;
+    let p1 = r * phi.cos();
+    let s = 0.5 * (1.0 + vh.z);
+    let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+    let nh = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * vh;
+    vec3(ax * nh.x, ay * nh.y, nh.z.max(1e-6)).norm()
+}
+
+// microfacet reflection without fresnel term
+fn ggx_reflection(wo: Vector3, wi: Vector3, roughness: (f64, f64)) -> f64 {
+    if wo.z <= 0.0 || wi.z <= 0.0 {
+        return 0.0;
     }
+    let wh = (wo + wi).norm();
+    ggx_distribution(wh, roughness) * ggx_g(wo, wi, roughness) / (4.0 * wo.z * wi.z)
 }
 
-fn ggx_distribution(wh: Vector3, normal: Vector3, roughness: f64) -> f64 {
-    let rough2 = math::pow2(roughness);
-    let dot_nh = normal.dot(wh);
-    rough2 / (math::PI * math::pow2(1.0 -(1.0 - rough2) * math::pow2(dot_nh)))
+fn ggx_sample_reflection(wo: Vector3, roughness: (f64, f64)) -> Option<Vector3> {
+    if wo.z <= 0.0 {
+        return None;
+    }
+    let wh = ggx_sample_visible_normal(wo, roughness);
+    Some(math::reflect(-wo, wh)).filter(|wi| wi.z > 0.0)
 }
 
-fn ggx_g(wo: Vector3, wi: Vector3, normal: Vector3, roughness: f64) -> f64 {
-    1.0 / (1.0 + ggx_g1(wo, normal, roughness) + ggx_g1(wi, normal, roughness))
+// pdf of reflected direction sampled by ggx_sample_reflection
+fn ggx_reflection_pdf(wo: Vector3, wi: Vector3, roughness: (f64, f64)) -> f64 {
+    if wo.z <= 0.0 || wi.z <= 0.0 {
+        return 0.0;
+    }
+    let wh = (wo + wi).norm();
+    ggx_distribution(wh, roughness) * ggx_g1(wo, roughness) / (4.0 * wo.z)
 }
 
-fn ggx_g1(v: Vector3, normal: Vector3, roughness: f64) -> f64 {
-    let rough2 = math::pow2(roughness);
-    let dot2 = math::pow2(normal.dot(v));
-    0.5 * (-1.0 + (1.0 + rough2 * (1.0 / dot2 - 1.0)).sqrt())
+const ALBEDO_TABLE_SIZE: usize = 32;
+
+// directional albedo of single scattering microfacet lobe without fresnel term, tabulated over
+// cosine of wo and roughness, for energy compensation of multiple scattering by Kulla and Conty.
+// it also tabulates albedo of sheen to attenuate material below it.
+struct AlbedoTable {
+    albedo: Vec<f64>,
+    average: Vec<f64>,
+}
+
+impl AlbedoTable {
+    // estimate gives weight of a reflected direction sampled from wo with roughness and random numbers
+    fn new<F: Fn(Vector3, f64, f64, f64) -> f64>(estimate: F) -> AlbedoTable {
+        let n = ALBEDO_TABLE_SIZE;
+        let samples = 32;
+        let grid = |i: usize| (i as f64 / (n - 1) as f64).max(1e-3);
+        let mut albedo = vec![0.0; n * n];
+        for j in 0..n {
+            let roughness = grid(j);
+            for i in 0..n {
+                let cosine = grid(i);
+                let wo = vec3((1.0 - cosine * cosine).sqrt(), 0.0, cosine);
+                let mut sum = 0.0;
+                for k in 0..(samples * samples) {
+                    let u1 = ((k / samples) as f64 + 0.5) / samples as f64;
+                    let u2 = ((k % samples) as f64 + 0.5) / samples as f64;
+                    sum += estimate(wo, roughness, u1, u2);
+                }
+                albedo[j * n + i] = math::clamp(sum / (samples * samples) as f64, 0.0, 1.0);
+            }
+        }
+        // cosine weighted average by trapezoidal rule
+        let average = (0..n).map(|j| {
+            (1..n).map(|i| {
+                let (c0, c1) = (grid(i - 1), grid(i));
+                (c1 - c0) * (albedo[j * n + i - 1] * c0 + albedo[j * n + i] * c1)
+            }).sum()
+        }).collect();
+        AlbedoTable { albedo, average }
+    }
+    fn albedo(&self, cosine: f64, roughness: f64) -> f64 {
+        let n = ALBEDO_TABLE_SIZE;
+        let (i, s) = albedo_table_index(cosine);
+        let (j, t) = albedo_table_index(roughness);
+        let a0 = (1.0 - s) * self.albedo[j * n + i] + s * self.albedo[j * n + i + 1];
+        let a1 = (1.0 - s) * self.albedo[(j + 1) * n + i] + s * self.albedo[(j + 1) * n + i + 1];
+        (1.0 - t) * a0 + t * a1
+    }
+    fn average(&self, roughness: f64) -> f64 {
+        let (j, t) = albedo_table_index(roughness);
+        (1.0 - t) * self.average[j] + t * self.average[j + 1]
+    }
+    // lobe compensating energy lost by single scattering
+    fn multiple_scattering(&self, wo: Vector3, wi: Vector3, roughness: f64) -> f64 {
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return 0.0;
+        }
+        let average = self.average(roughness);
+        if average >= 1.0 {
+            return 0.0;
+        }
+        (1.0 - self.albedo(wo.z, roughness)) * (1.0 - self.albedo(wi.z, roughness)) / (math::PI * (1.0 - average))
+    }
+}
+
+// lower index and interpolation factor of table
+fn albedo_table_index(v: f64) -> (usize, f64) {
+    let x = math::clamp(v, 0.0, 1.0) * (ALBEDO_TABLE_SIZE - 1) as f64;
+    let i = (x as usize).min(ALBEDO_TABLE_SIZE - 2);
+    (i, x - i as f64)
+}
+
+// scale of multiple scattering lobe by average fresnel reflectance
+fn multiple_scattering_fresnel(f_avg: Vector3, e_avg: f64) -> Vector3 {
+    f_avg * f_avg * e_avg / (1.0 - f_avg * (1.0 - e_avg))
+}
+
+fn ggx_albedo_table() -> &'static AlbedoTable {
+    static TABLE: OnceLock<AlbedoTable> = OnceLock::new();
+    TABLE.get_or_init(|| AlbedoTable::new(|wo, roughness, u1, u2| {
+        let roughness = (roughness, roughness);
+        let wh = ggx_visible_normal(wo, roughness, u1, u2);
+        let wi = math::reflect(-wo, wh);
+        if wi.z <= 0.0 { 0.0 } else { ggx_g(wo, wi, roughness) / ggx_g1(wo, roughness) }
+    }))
+}
+
+fn isotropic_roughness(roughness: (f64, f64)) -> f64 {
+    (roughness.0 * roughness.1).sqrt()
+}
+
+fn ggx_multiple_scattering(reflectance: Vector3, roughness: (f64, f64), wo: Vector3, wi: Vector3) -> Vector3 {
+    let table = ggx_albedo_table();
+    let roughness = isotropic_roughness(roughness);
+    let scale = multiple_scattering_fresnel(reflectance, table.average(roughness));
+    scale * table.multiple_scattering(wo, wi, roughness)
 }
 
 impl Material for MicrofacetReflectionMaterial {
     fn bsdf(&self, isec: &Intersection, wi: Vector3) -> Vector3 {
-        let wh = (isec.wo + wi).norm();
-        let d = ggx_distribution(wh, isec.normal, self.roughness);
-        let g = ggx_g(isec.wo, wi, isec.normal, self.roughness);
+        let wo = isec.to_local(isec.wo);
+        let wi = isec.to_local(wi);
+        let reflectance = self.reflectance.value(isec);
+        let roughness = self.roughness(isec);
         let f = 1.0;
-        let dot_no = isec.normal.dot(isec.wo);
-        let dot_ni = isec.normal.dot(wi);
-        self.reflectance * d * g * f / (4.0 * dot_no * dot_ni)
+        reflectance * f * ggx_reflection(wo, wi, roughness) + ggx_multiple_scattering(reflectance, roughness, wo, wi)
     }
     fn sample(&self, isec: &Intersection) -> Option<Bsdf> {
-        let (dir, pdf) = math::sample_random_cosine_dir();
-        let wi = math::change_basis(dir, isec.normal);
+        let wo = isec.to_local(isec.wo);
+        if wo.z <= 0.0 {
+            return None;
+        }
+        // multiple scattering lobe is sampled by cosine with probability of lost energy
+        let roughness = self.roughness(isec);
+        let albedo = ggx_albedo_table().albedo(wo.z, isotropic_roughness(roughness));
+        let wi = if math::random() < albedo {
+            ggx_sample_reflection(wo, roughness)?
+        } else {
+            math::sample_random_cosine_dir().0
+        };
+        let wi = isec.to_world(wi);
         let value = self.bsdf(isec, wi);
-        Some(Bsdf { value, wi, pdf })
-    }
-}
-
-// pub struct MicrofacetTransmissionMaterial {
-//     transmittance: Vector3,
-//     ri: f64,
-//     roughness: f64,
-// }
-
-// impl MicrofacetTransmissionMaterial {
-//     pub fn new(transmittance: Vector3, ri: f64, roughness: f64) -> MicrofacetTransmissionMaterial {
-//         MicrofacetTransmissionMaterial { transmittance, ri, roughness }
-//     }
-// }
-
-// impl Material for MicrofacetTransmissionMaterial {
-//     fn bsdf(&self, isec: &Intersection, wi: Vector3) -> Vector3 {
-//         let dot = isec.normal.dot(isec.wo);
-//         let (ri, normal) = if dot > 0.0 {
-//             (self.ri, isec.normal)
-//         } else {
-//             (1.0 / self.ri, -isec.normal)
-//         };
-//         let wh = (isec.wo + ri * wi).norm();
-
-//         let dot_no = normal.dot(isec.wo);
-//         let dot_ni = normal.dot(wi);
-//         let dot_ho = wh.dot(isec.wo);
-//         let dot_hi = wh.dot(wi);
-
-//         let d = ggx_distribution(wh, normal, self.roughness);
-//         let g = ggx_g(isec.wo, wi, normal, self.roughness);
-//         let f = math::schlick_fresnel(dot_no, ri);
-
-//         self.transmittance * math::pow2(self.ri) * d * g * (1.0 - f) * dot_ho.abs() * dot_hi.abs()
-//             / (math::pow2(dot_ho + self.ri * dot_hi) * dot_no * dot_ni)
-//     }
-//     fn sample(&self, isec: &Intersection) -> Option<Bsdf> {
-//         let (dir, pdf) = math::sample_random_cosine_dir();
-//         let normal = if isec.normal.dot(isec.wo) > 0.0 { isec.normal } else { -isec.normal };
-//         let wi = math::change_basis(dir, normal);
-//         let value = self.bsdf(isec, wi);
-//         Some(Bsdf { value, wi, pdf })
-//     }
-// }
+        let pdf = self.pdf(isec, wi);
+        Some(Bsdf { value, wi, pdf, specular: false })
+    }
+    fn pdf(&self, isec: &Intersection, wi: Vector3) -> f64 {
+        let wo = isec.to_local(isec.wo);
+        let wi = isec.to_local(wi);
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return 0.0;
+        }
+        let roughness = self.roughness(isec);
+        let albedo = ggx_albedo_table().albedo(wo.z, isotropic_roughness(roughness));
+        albedo * ggx_reflection_pdf(wo, wi, roughness) + (1.0 - albedo) * wi.z / math::PI
+    }
+}
+
+// rough dielectric interface by Walter et al.
+// wo is in upper hemisphere and eta is relative index of refraction of lower side.
+fn rough_dielectric_bsdf(wo: Vector3, wi: Vector3, eta: f64, roughness: (f64, f64)) -> f64 {
+    if wo.z <= 0.0 || wi.z == 0.0 {
+        return 0.0;
+    }
+    if wi.z > 0.0 {
+        let wh = (wo + wi).norm();
+        let f = math::fresnel_dielectric(wo.dot(wh), eta);
+        f * ggx_reflection(wo, wi, roughness)
+    } else {
+        let wh = match rough_dielectric_half_vector(wo, wi, eta) {
+            Some(wh) => wh,
+            None => return 0.0,
+        };
+        let dot_ho = wh.dot(wo);
+        let dot_hi = wh.dot(wi);
+        let d = ggx_distribution(wh, roughness);
+        let g = ggx_g(wo, wi, roughness);
+        let f = math::fresnel_dielectric(dot_ho, eta);
+        let denom = math::pow2(dot_hi + dot_ho / eta);
+        // radiance is scaled by square of relative index of refraction
+        d * g * (1.0 - f) * (dot_hi * dot_ho / (wi.z * wo.z * denom)).abs() / math::pow2(eta)
+    }
+}
+
+fn rough_dielectric_pdf(wo: Vector3, wi: Vector3, eta: f64, roughness: (f64, f64)) -> f64 {
+    let (pdf, probability) = rough_dielectric_lobe_pdf(wo, wi, eta, roughness);
+    pdf * probability
+}
+
+// pdf of wi when lobe of reflection or transmission is already chosen, and probability of choosing the lobe
+fn rough_dielectric_lobe_pdf(wo: Vector3, wi: Vector3, eta: f64, roughness: (f64, f64)) -> (f64, f64) {
+    if wo.z <= 0.0 || wi.z == 0.0 {
+        return (0.0, 0.0);
+    }
+    if wi.z > 0.0 {
+        let wh = (wo + wi).norm();
+        let f = math::fresnel_dielectric(wo.dot(wh), eta);
+        (ggx_reflection_pdf(wo, wi, roughness), f)
+    } else {
+        let wh = match rough_dielectric_half_vector(wo, wi, eta) {
+            Some(wh) => wh,
+            None => return (0.0, 0.0),
+        };
+        let dot_ho = wh.dot(wo);
+        let dot_hi = wh.dot(wi);
+        let f = math::fresnel_dielectric(dot_ho, eta);
+        let pdf_wh = ggx_distribution(wh, roughness) * ggx_g1(wo, roughness) * dot_ho / wo.z;
+        let jacobian = dot_hi.abs() / math::pow2(dot_hi + dot_ho / eta);
+        (pdf_wh * jacobian, 1.0 - f)
+    }
+}
+
+fn rough_dielectric_sample(wo: Vector3, eta: f64, roughness: (f64, f64)) -> Option<Vector3> {
+    let wh = ggx_sample_visible_normal(wo, roughness);
+    let f = math::fresnel_dielectric(wo.dot(wh), eta);
+    if math::random() < f {
+        Some(math::reflect(-wo, wh)).filter(|wi| wi.z > 0.0)
+    } else {
+        math::refract(wo, wh, 1.0 / eta).filter(|wi| wi.z < 0.0)
+    }
+}
+
+// generalized half vector of refraction in upper hemisphere
+fn rough_dielectric_half_vector(wo: Vector3, wi: Vector3, eta: f64) -> Option<Vector3> {
+    let wh = (wo + eta * wi).norm();
+    let wh = if wh.z < 0.0 { -wh } else { wh };
+    // discard back facing microfacets
+    if wh.dot(wo) <= 0.0 || wh.dot(wi) >= 0.0 {
+        None
+    } else {
+        Some(wh)
+    }
+}
+
+pub struct MicrofacetTransmissionMaterial {
+    transmittance: Arc<PTexture<Vector3>>,
+    ri: Arc<PTexture<f64>>,
+    roughness: Arc<PTexture<f64>>,
+}
+
+impl MicrofacetTransmissionMaterial {
+    pub fn new(transmittance: Vector3, ri: f64, roughness: f64) -> MicrofacetTransmissionMaterial {
+        MicrofacetTransmissionMaterial::textured(constant(transmittance), constant(ri), constant(roughness))
+    }
+    pub fn textured(transmittance: Arc<PTexture<Vector3>>, ri: Arc<PTexture<f64>>, roughness: Arc<PTexture<f64>>) -> MicrofacetTransmissionMaterial {
+        MicrofacetTransmissionMaterial { transmittance, ri, roughness }
+    }
+    fn roughness(&self, isec: &Intersection) -> (f64, f64) {
+        let roughness = self.roughness.value(isec).max(1e-3);
+        (roughness, roughness)
+    }
+    // local direction seen from the side of wo, and relative index of refraction of the other side
+    fn local(&self, isec: &Intersection, v: Vector3) -> (Vector3, f64) {
+        let v = isec.to_local(v);
+        let ri = self.ri.value(isec);
+        if isec.normal.dot(isec.wo) > 0.0 {
+            (v, ri)
+        } else {
+            (vec3(v.x, v.y, -v.z), 1.0 / ri)
+        }
+    }
+}
+
+impl Material for MicrofacetTransmissionMaterial {
+    fn bsdf(&self, isec: &Intersection, wi: Vector3) -> Vector3 {
+        let (wo, eta) = self.local(isec, isec.wo);
+        let (wi, _) = self.local(isec, wi);
+        let value = rough_dielectric_bsdf(wo, wi, eta, self.roughness(isec));
+        if wi.z > 0.0 {
+            Vector3::one() * value
+        } else {
+            self.transmittance.value(isec) * value
+        }
+    }
+    fn sample(&self, isec: &Intersection) -> Option<Bsdf> {
+        let (wo, eta) = self.local(isec, isec.wo);
+        let wi = rough_dielectric_sample(wo, eta, self.roughness(isec))?;
+        let wi = if isec.normal.dot(isec.wo) > 0.0 { wi } else { vec3(wi.x, wi.y, -wi.z) };
+        let wi = isec.to_world(wi);
+        let pdf = self.pdf(isec, wi);
+        if pdf == 0.0 {
+            return None;
+        }
+        let value = self.bsdf(isec, wi);
+        Some(Bsdf { value, wi, pdf, specular: false })
+    }
+    fn pdf(&self, isec: &Intersection, wi: Vector3) -> f64 {
+        let (wo, eta) = self.local(isec, isec.wo);
+        let (wi, _) = self.local(isec, wi);
+        rough_dielectric_pdf(wo, wi, eta, self.roughness(isec))
+    }
+}
+
+// complex index of refraction of metal at wavelengths of red, green and blue
+#[derive(Debug, Clone, Copy)]
+pub struct Conductor {
+    pub eta: Vector3,
+    pub k: Vector3,
+}
+
+impl Conductor {
+    pub fn new(eta: Vector3, k: Vector3) -> Conductor {
+        Conductor { eta, k }
+    }
+    pub fn gold() -> Conductor {
+        Conductor::new(vec3(0.143, 0.374, 1.442), vec3(3.983, 2.385, 1.603))
+    }
+    pub fn copper() -> Conductor {
+        Conductor::new(vec3(0.200, 0.924, 1.102), vec3(3.912, 2.452, 2.142))
+    }
+    pub fn aluminium() -> Conductor {
+        Conductor::new(vec3(1.657, 0.880, 0.521), vec3(9.224, 6.270, 4.837))
+    }
+    pub fn silver() -> Conductor {
+        Conductor::new(vec3(0.155, 0.117, 0.138), vec3(4.828, 3.122, 2.147))
+    }
+    pub fn iron() -> Conductor {
+        Conductor::new(vec3(2.912, 2.950, 2.585), vec3(3.077, 2.932, 2.767))
+    }
+    pub fn fresnel(&self, cosine: f64) -> Vector3 {
+        let cosine = math::clamp(cosine, 0.0, 1.0);
+        vec3(
+            math::fresnel_conductor(cosine, self.eta.x, self.k.x),
+            math::fresnel_conductor(cosine, self.eta.y, self.k.y),
+            math::fresnel_conductor(cosine, self.eta.z, self.k.z)
+        )
+    }
+}
+
+// metal surface which is perfectly specular when roughness is zero
+pub struct ConductorMaterial {
+    eta: Arc<PTexture<Vector3>>,
+    k: Arc<PTexture<Vector3>>,
+    roughness: (Arc<PTexture<f64>>, Arc<PTexture<f64>>),
+}
+
+impl ConductorMaterial {
+    pub fn new(conductor: Conductor, roughness: f64) -> ConductorMaterial {
+        ConductorMaterial::anisotropic(conductor, roughness, roughness)
+    }
+    // roughness along tangent and bitangent of intersection
+    pub fn anisotropic(conductor: Conductor, roughness_u: f64, roughness_v: f64) -> ConductorMaterial {
+        ConductorMaterial::textured(constant(conductor.eta), constant(conductor.k), constant(roughness_u), constant(roughness_v))
+    }
+    // complex index of refraction is given by textures of eta and k
+    pub fn textured(eta: Arc<PTexture<Vector3>>, k: Arc<PTexture<Vector3>>, roughness_u: Arc<PTexture<f64>>, roughness_v: Arc<PTexture<f64>>) -> ConductorMaterial {
+        ConductorMaterial { eta, k, roughness: (roughness_u, roughness_v) }
+    }
+    fn conductor(&self, isec: &Intersection) -> Conductor {
+        Conductor::new(self.eta.value(isec), self.k.value(isec))
+    }
+}
+
+fn is_specular(roughness: (f64, f64)) -> bool {
+    roughness.0.max(roughness.1) < 1e-3
+}
+
+impl Material for ConductorMaterial {
+    fn bsdf(&self, isec: &Intersection, wi: Vector3) -> Vector3 {
+        let roughness = roughness_at(&self.roughness, isec);
+        if is_specular(roughness) {
+            return Vector3::zero();
+        }
+        let wo = isec.to_local(isec.wo);
+        let wi = isec.to_local(wi);
+        let f = self.conductor(isec).fresnel(wo.dot((wo + wi).norm()));
+        f * ggx_reflection(wo, wi, roughness)
+    }
+    fn sample(&self, isec: &Intersection) -> Option<Bsdf> {
+        let roughness = roughness_at(&self.roughness, isec);
+        if is_specular(roughness) {
+            let dot_no = isec.normal.dot(isec.wo);
+            if dot_no <= 0.0 {
+                return None;
+            }
+            let wi = math::reflect(-isec.wo, isec.normal);
+            let value = self.conductor(isec).fresnel(dot_no) / dot_no;
+            return Some(Bsdf { value, wi, pdf: 1.0, specular: true });
+        }
+        let wi = ggx_sample_reflection(isec.to_local(isec.wo), roughness)?;
+        let wi = isec.to_world(wi);
+        let value = self.bsdf(isec, wi);
+        let pdf = self.pdf(isec, wi);
+        Some(Bsdf { value, wi, pdf, specular: false })
+    }
+    fn pdf(&self, isec: &Intersection, wi: Vector3) -> f64 {
+        let roughness = roughness_at(&self.roughness, isec);
+        if is_specular(roughness) {
+            return 0.0;
+        }
+        ggx_reflection_pdf(isec.to_local(isec.wo), isec.to_local(wi), roughness)
+    }
+}
 
 pub struct FresnelBlendMaterial {
-    diffuse: Vector3,
-    specular: Vector3,
-    roughness: f64,
+    diffuse: Arc<PTexture<Vector3>>,
+    specular: Arc<PTexture<Vector3>>,
+    roughness: Arc<PTexture<f64>>,
 }
 
 impl FresnelBlendMaterial {
     pub fn new(diffuse: Vector3, specular: Vector3, roughness: f64) -> FresnelBlendMaterial {
+        FresnelBlendMaterial::textured(constant(diffuse), constant(specular), constant(roughness))
+    }
+    pub fn textured(diffuse: Arc<PTexture<Vector3>>, specular: Arc<PTexture<Vector3>>, roughness: Arc<PTexture<f64>>) -> FresnelBlendMaterial {
         FresnelBlendMaterial { diffuse, specular, roughness }
     }
-    fn schlick_fresnel(&self, cosine: f64) -> Vector3 {
-        self.specular + (1.0 - self.specular) * math::pow5(1.0 - cosine)
+    fn roughness(&self, isec: &Intersection) -> f64 {
+        self.roughness.value(isec).max(1e-3)
     }
 }
 
+// diffuse term of Ashikhmin-Shirley model, which is reduced by reflectance of the specular layer
+fn fresnel_blend_diffuse(diffuse: Vector3, specular: Vector3, wo: Vector3, wi: Vector3) -> Vector3 {
+    (28.0 / (23.0 * math::PI)) * diffuse * (1.0 - specular)
+        * (1.0 - math::pow5(1.0 - 0.5 * wo.z)) * (1.0 - math::pow5(1.0 - 0.5 * wi.z))
+}
+
+fn ashikhmin_shirley_multiple_scattering(specular: Vector3, roughness: f64, wo: Vector3, wi: Vector3) -> Vector3 {
+    let table = ashikhmin_shirley_albedo_table();
+    // average of schlick fresnel over hemisphere
+    let f_avg = specular + (1.0 - specular) / 21.0;
+    let scale = multiple_scattering_fresnel(f_avg, table.average(roughness));
+    scale * table.multiple_scattering(wo, wi, roughness)
+}
+
+fn ashikhmin_shirley_albedo_table() -> &'static AlbedoTable {
+    static TABLE: OnceLock<AlbedoTable> = OnceLock::new();
+    TABLE.get_or_init(|| AlbedoTable::new(|wo, roughness, u1, u2| {
+        let wh = ggx_visible_normal(wo, (roughness, roughness), u1, u2);
+        let wi = math::reflect(-wo, wh);
+        if wi.z <= 0.0 {
+            return 0.0;
+        }
+        // specular lobe divided by pdf of visible normal sampling
+        wi.z * wo.z / (wh.dot(wi) * wo.z.max(wi.z) * ggx_g1(wo, (roughness, roughness)))
+    }))
+}
+
 impl Material for FresnelBlendMaterial {
     fn bsdf(&self, isec: &Intersection, wi: Vector3) -> Vector3 {
-        let wh = (isec.wo + wi).norm();
-        let dot_no = isec.normal.dot(isec.wo);
-        let dot_ni = isec.normal.dot(wi);
+        let wo = isec.to_local(isec.wo);
+        let wi = isec.to_local(wi);
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return Vector3::zero();
+        }
+        let wh = (wo + wi).norm();
         let dot_hi = wh.dot(wi);
+        let specular = self.specular.value(isec);
+        let roughness = self.roughness(isec);
 
-        let diffuse = (28.0 / (23.0 * math::PI)) * self.diffuse * (1.0 - self.specular)
-            * (1.0 - math::pow5(1.0 - 0.5 * dot_no)) * (1.0 - math::pow5(1.0 - 0.5 * dot_ni));
-        let d = ggx_distribution(wh, isec.normal, self.roughness);
-        let f = self.schlick_fresnel(dot_hi);
-        let specular = d * f / (4.0 * dot_hi * dot_no.max(dot_ni));
-        diffuse + specular
+        let diffuse = fresnel_blend_diffuse(self.diffuse.value(isec), specular, wo, wi);
+        let d = ggx_distribution(wh, (roughness, roughness));
+        let f = specular + (1.0 - specular) * math::pow5(1.0 - dot_hi);
+        diffuse + d * f / (4.0 * dot_hi * wo.z.max(wi.z)) + ashikhmin_shirley_multiple_scattering(specular, roughness, wo, wi)
     }
     fn sample(&self, isec: &Intersection) -> Option<Bsdf> {
-        let (dir, pdf) = math::sample_random_cosine_dir();
-        let wi = math::change_basis(dir, isec.normal);
+        let wo = isec.to_local(isec.wo);
+        if wo.z <= 0.0 {
+            return None;
+        }
+        // choose diffuse or specular lobe with equal probability
+        let roughness = self.roughness(isec);
+        let wi = if math::random() < 0.5 {
+            math::sample_random_cosine_dir().0
+        } else {
+            ggx_sample_reflection(wo, (roughness, roughness))?
+        };
+        if wi.z <= 0.0 {
+            return None;
+        }
+        let wi = isec.to_world(wi);
+        let value = self.bsdf(isec, wi);
+        let pdf = self.pdf(isec, wi);
+        Some(Bsdf { value, wi, pdf, specular: false })
+    }
+    fn pdf(&self, isec: &Intersection, wi: Vector3) -> f64 {
+        let wo = isec.to_local(isec.wo);
+        let wi = isec.to_local(wi);
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return 0.0;
+        }
+        let roughness = self.roughness(isec);
+        0.5 * (wi.z / math::PI + ggx_reflection_pdf(wo, wi, (roughness, roughness)))
+    }
+}
+
+fn lerp(a: Vector3, b: Vector3, t: f64) -> Vector3 {
+    (1.0 - t) * a + t * b
+}
+
+fn schlick_weight(cosine: f64) -> f64 {
+    math::pow5(1.0 - math::clamp(cosine, 0.0, 1.0))
+}
+
+// generalized trowbridge-reitz distribution with gamma = 1 used by clearcoat
+fn gtr1_distribution(wh: Vector3, alpha: f64) -> f64 {
+    let a2 = alpha * alpha;
+    (a2 - 1.0) / (math::PI * a2.ln() * (1.0 + (a2 - 1.0) * math::pow2(wh.z)))
+}
+
+fn gtr1_sample_normal(alpha: f64) -> Vector3 {
+    let a2 = alpha * alpha;
+    let cos2 = (1.0 - a2.powf(1.0 - math::random())) / (1.0 - a2);
+    let sin = (1.0 - cos2).max(0.0).sqrt();
+    let phi = math::TWO_PI * math::random();
+    vec3(sin * phi.cos(), sin * phi.sin(), cos2.sqrt())
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PrincipledParameters {
+    pub base_color: Vector3,
+    pub metallic: f64,
+    pub roughness: f64,
+    pub specular: f64,
+    pub specular_tint: f64,
+    pub sheen: f64,
+    pub sheen_tint: f64,
+    pub clearcoat: f64,
+    pub clearcoat_gloss: f64,
+    pub transmission: f64,
+    pub ior: f64,
+    pub subsurface: f64,
+}
+
+impl Default for PrincipledParameters {
+    fn default() -> PrincipledParameters {
+        PrincipledParameters {
+            base_color: vec3(0.8, 0.8, 0.8),
+            metallic: 0.0,
+            roughness: 0.5,
+            specular: 0.5,
+            specular_tint: 0.0,
+            sheen: 0.0,
+            sheen_tint: 0.5,
+            clearcoat: 0.0,
+            clearcoat_gloss: 1.0,
+            transmission: 0.0,
+            ior: 1.5,
+            subsurface: 0.0,
+        }
+    }
+}
+
+// textures of each parameter of principled material
+pub struct PrincipledTextures {
+    pub base_color: Arc<PTexture<Vector3>>,
+    pub metallic: Arc<PTexture<f64>>,
+    pub roughness: Arc<PTexture<f64>>,
+    pub specular: Arc<PTexture<f64>>,
+    pub specular_tint: Arc<PTexture<f64>>,
+    pub sheen: Arc<PTexture<f64>>,
+    pub sheen_tint: Arc<PTexture<f64>>,
+    pub clearcoat: Arc<PTexture<f64>>,
+    pub clearcoat_gloss: Arc<PTexture<f64>>,
+    pub transmission: Arc<PTexture<f64>>,
+    pub ior: Arc<PTexture<f64>>,
+    pub subsurface: Arc<PTexture<f64>>,
+}
+
+impl PrincipledTextures {
+    // constant textures of parameters
+    pub fn new(params: PrincipledParameters) -> PrincipledTextures {
+        PrincipledTextures {
+            base_color: constant(params.base_color),
+            metallic: constant(params.metallic),
+            roughness: constant(params.roughness),
+            specular: constant(params.specular),
+            specular_tint: constant(params.specular_tint),
+            sheen: constant(params.sheen),
+            sheen_tint: constant(params.sheen_tint),
+            clearcoat: constant(params.clearcoat),
+            clearcoat_gloss: constant(params.clearcoat_gloss),
+            transmission: constant(params.transmission),
+            ior: constant(params.ior),
+            subsurface: constant(params.subsurface),
+        }
+    }
+    fn evaluate(&self, isec: &Intersection) -> PrincipledParameters {
+        PrincipledParameters {
+            base_color: self.base_color.value(isec),
+            metallic: self.metallic.value(isec),
+            roughness: self.roughness.value(isec),
+            specular: self.specular.value(isec),
+            specular_tint: self.specular_tint.value(isec),
+            sheen: self.sheen.value(isec),
+            sheen_tint: self.sheen_tint.value(isec),
+            clearcoat: self.clearcoat.value(isec),
+            clearcoat_gloss: self.clearcoat_gloss.value(isec),
+            transmission: self.transmission.value(isec),
+            ior: self.ior.value(isec),
+            subsurface: self.subsurface.value(isec),
+        }
+    }
+}
+
+// disney principled bsdf by Burley, whose diffuse lobe is that of FresnelBlendMaterial.
+// transmissive part is rough dielectric and subsurface is approximated by diffuse lobe of Hanrahan-Krueger.
+pub struct PrincipledMaterial {
+    textures: PrincipledTextures,
+}
+
+impl PrincipledMaterial {
+    pub fn new(params: PrincipledParameters) -> PrincipledMaterial {
+        PrincipledMaterial::textured(PrincipledTextures::new(params))
+    }
+    pub fn textured(textures: PrincipledTextures) -> PrincipledMaterial {
+        PrincipledMaterial { textures }
+    }
+}
+
+// lobes of principled material evaluated with parameters at intersection
+impl PrincipledParameters {
+    fn alpha(&self) -> (f64, f64) {
+        let alpha = math::pow2(self.roughness).max(1e-3);
+        (alpha, alpha)
+    }
+    fn tint(&self) -> Vector3 {
+        let luminance = color::luminance(self.base_color);
+        if luminance > 0.0 { self.base_color / luminance } else { Vector3::one() }
+    }
+    // weights of diffuse, specular, clearcoat and transmission lobes
+    fn weights(&self) -> (f64, f64, f64, f64) {
+        let p = self;
+        let transmission = (1.0 - p.metallic) * p.transmission;
+        ((1.0 - p.metallic) * (1.0 - p.transmission), 1.0 - transmission, 0.25 * p.clearcoat, transmission)
+    }
+    // probabilities of choosing each lobe in sampling
+    fn probabilities(&self) -> (f64, f64, f64, f64) {
+        let (d, s, c, t) = self.weights();
+        let sum = d + s + c + t;
+        (d / sum, s / sum, c / sum, t / sum)
+    }
+    // reflectance of dielectric specular at normal incidence
+    fn dielectric_specular(&self) -> Vector3 {
+        self.specular * 0.08 * lerp(Vector3::one(), self.tint(), self.specular_tint)
+    }
+    fn diffuse(&self, wo: Vector3, wi: Vector3) -> Vector3 {
+        let p = self;
+        let wh = (wo + wi).norm();
+        let cos_d = wi.dot(wh);
+        let fl = schlick_weight(wi.z);
+        let fv = schlick_weight(wo.z);
+
+        let fss90 = cos_d * cos_d * p.roughness;
+        let fss = (1.0 + (fss90 - 1.0) * fl) * (1.0 + (fss90 - 1.0) * fv);
+        let ss = 1.25 * (fss * (1.0 / (wi.z + wo.z) - 0.5) + 0.5);
+
+        let diffuse = (1.0 - p.subsurface) * fresnel_blend_diffuse(p.base_color, self.dielectric_specular(), wo, wi)
+            + p.subsurface * p.base_color * math::INV_PI * ss;
+        let sheen = p.sheen * lerp(Vector3::one(), self.tint(), p.sheen_tint) * schlick_weight(cos_d);
+        diffuse + sheen
+    }
+    fn specular(&self, wo: Vector3, wi: Vector3) -> Vector3 {
+        let p = self;
+        let color = lerp(self.dielectric_specular(), p.base_color, p.metallic);
+        let cos_d = wo.dot((wo + wi).norm());
+        let f = color + (1.0 - color) * schlick_weight(cos_d);
+        f * ggx_reflection(wo, wi, self.alpha())
+    }
+    fn clearcoat_alpha(&self) -> f64 {
+        0.1 + (0.001 - 0.1) * self.clearcoat_gloss
+    }
+    fn clearcoat(&self, wo: Vector3, wi: Vector3) -> f64 {
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return 0.0;
+        }
+        let wh = (wo + wi).norm();
+        let d = gtr1_distribution(wh, self.clearcoat_alpha());
+        let f = 0.04 + 0.96 * schlick_weight(wo.dot(wh));
+        let g = ggx_g1(wo, (0.25, 0.25)) * ggx_g1(wi, (0.25, 0.25));
+        d * f * g / (4.0 * wo.z * wi.z)
+    }
+    fn clearcoat_pdf(&self, wo: Vector3, wi: Vector3) -> f64 {
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return 0.0;
+        }
+        let wh = (wo + wi).norm();
+        gtr1_distribution(wh, self.clearcoat_alpha()) * wh.z / (4.0 * wo.dot(wh))
+    }
+    fn transmission(&self, wo: Vector3, wi: Vector3, eta: f64) -> Vector3 {
+        let value = rough_dielectric_bsdf(wo, wi, eta, self.alpha());
+        if wi.z > 0.0 {
+            Vector3::one() * value
+        } else {
+            // tint is applied at both of entering and exiting
+            Vector3::new(self.base_color.x.sqrt(), self.base_color.y.sqrt(), self.base_color.z.sqrt()) * value
+        }
+    }
+}
+
+impl Material for PrincipledMaterial {
+    fn bsdf(&self, isec: &Intersection, wi: Vector3) -> Vector3 {
+        let p = self.textures.evaluate(isec);
+        let wo = isec.to_local(isec.wo);
+        let wi = isec.to_local(wi);
+        let (diffuse, specular, clearcoat, transmission) = p.weights();
+        if wo.z < 0.0 {
+            // only transmissive part can be seen from inside
+            let flip = |v: Vector3| vec3(v.x, v.y, -v.z);
+            return transmission * p.transmission(flip(wo), flip(wi), 1.0 / p.ior);
+        }
+        let mut value = transmission * p.transmission(wo, wi, p.ior);
+        if wi.z > 0.0 {
+            value += diffuse * p.diffuse(wo, wi);
+            value += specular * p.specular(wo, wi);
+            value += Vector3::one() * clearcoat * p.clearcoat(wo, wi);
+        }
+        value
+    }
+    fn sample(&self, isec: &Intersection) -> Option<Bsdf> {
+        let p = self.textures.evaluate(isec);
+        let wo = isec.to_local(isec.wo);
+        let wi = if wo.z < 0.0 {
+            let flip = |v: Vector3| vec3(v.x, v.y, -v.z);
+            flip(rough_dielectric_sample(flip(wo), 1.0 / p.ior, p.alpha())?)
+        } else {
+            let (diffuse, specular, clearcoat, _) = p.probabilities();
+            let r = math::random();
+            if r < diffuse {
+                math::sample_random_cosine_dir().0
+            } else if r < diffuse + specular {
+                ggx_sample_reflection(wo, p.alpha())?
+            } else if r < diffuse + specular + clearcoat {
+                let wh = gtr1_sample_normal(p.clearcoat_alpha());
+                math::reflect(-wo, wh)
+            } else {
+                rough_dielectric_sample(wo, p.ior, p.alpha())?
+            }
+        };
+        let wi = isec.to_world(wi);
+        let pdf = self.pdf(isec, wi);
+        if pdf == 0.0 {
+            return None;
+        }
         let value = self.bsdf(isec, wi);
-        Some(Bsdf { value, wi, pdf })
+        Some(Bsdf { value, wi, pdf, specular: false })
+    }
+    fn pdf(&self, isec: &Intersection, wi: Vector3) -> f64 {
+        let p = self.textures.evaluate(isec);
+        let wo = isec.to_local(isec.wo);
+        let wi = isec.to_local(wi);
+        if wo.z < 0.0 {
+            let flip = |v: Vector3| vec3(v.x, v.y, -v.z);
+            let (_, _, _, transmission) = p.weights();
+            return if transmission > 0.0 {
+                rough_dielectric_pdf(flip(wo), flip(wi), 1.0 / p.ior, p.alpha())
+            } else {
+                0.0
+            };
+        }
+        let (diffuse, specular, clearcoat, transmission) = p.probabilities();
+        let mut pdf = transmission * rough_dielectric_pdf(wo, wi, p.ior, p.alpha());
+        if wi.z > 0.0 {
+            pdf += diffuse * wi.z * math::INV_PI;
+            pdf += specular * ggx_reflection_pdf(wo, wi, p.alpha());
+            pdf += clearcoat * p.clearcoat_pdf(wo, wi);
+        }
+        pdf
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Lobe {
+    All,
+    Reflection,
+    Transmission,
+}
+
+// scattering at interface of layers in local space
+struct LayerSample {
+    wi: Vector3,
+    value: Vector3,
+    pdf: f64,
+    specular: bool,
+}
+
+// flips v to the side where wo is in upper hemisphere
+fn upper(wo: Vector3, v: Vector3) -> Vector3 {
+    if wo.z < 0.0 { vec3(v.x, v.y, -v.z) } else { v }
+}
+
+// dielectric interface on top of layered material. light can hit it from both sides.
+struct Coat {
+    ior: f64,
+    roughness: (f64, f64),
+}
+
+impl Coat {
+    fn is_specular(&self) -> bool {
+        is_specular(self.roughness)
+    }
+    fn eta(&self, wo: Vector3) -> f64 {
+        if wo.z > 0.0 { self.ior } else { 1.0 / self.ior }
+    }
+    fn bsdf(&self, wo: Vector3, wi: Vector3) -> f64 {
+        if self.is_specular() {
+            return 0.0;
+        }
+        rough_dielectric_bsdf(upper(wo, wo), upper(wo, wi), self.eta(wo), self.roughness)
+    }
+    fn pdf(&self, wo: Vector3, wi: Vector3, lobe: Lobe) -> f64 {
+        if self.is_specular() {
+            return 0.0;
+        }
+        let (pdf, probability) = rough_dielectric_lobe_pdf(upper(wo, wo), upper(wo, wi), self.eta(wo), self.roughness);
+        match lobe {
+            Lobe::All => pdf * probability,
+            Lobe::Reflection if wo.z * wi.z > 0.0 => pdf,
+            Lobe::Transmission if wo.z * wi.z < 0.0 => pdf,
+            _ => 0.0,
+        }
+    }
+    fn sample(&self, wo: Vector3, lobe: Lobe) -> Option<LayerSample> {
+        let eta = self.eta(wo);
+        let v = upper(wo, wo);
+        if v.z == 0.0 {
+            return None;
+        }
+        let wh = if self.is_specular() { vec3(0.0, 0.0, 1.0) } else { ggx_sample_visible_normal(v, self.roughness) };
+        let f = math::fresnel_dielectric(v.dot(wh), eta);
+        let reflection = match lobe {
+            Lobe::All => math::random() < f,
+            Lobe::Reflection => true,
+            Lobe::Transmission => false,
+        };
+        let wi = if reflection {
+            Some(math::reflect(-v, wh)).filter(|wi| wi.z > 0.0)?
+        } else {
+            math::refract(v, wh, 1.0 / eta).filter(|wi| wi.z < 0.0)?
+        };
+        if !self.is_specular() {
+            let wi = upper(wo, wi);
+            let pdf = self.pdf(wo, wi, lobe);
+            if pdf == 0.0 {
+                return None;
+            }
+            return Some(LayerSample { wi, value: Vector3::one() * self.bsdf(wo, wi), pdf, specular: false });
+        }
+        let (value, probability) = if reflection { (f, f) } else { ((1.0 - f) / math::pow2(eta), 1.0 - f) };
+        let pdf = if lobe == Lobe::All { probability } else { 1.0 };
+        if value == 0.0 {
+            return None;
+        }
+        Some(LayerSample { wi: upper(wo, wi), value: Vector3::one() * value / wi.z.abs(), pdf, specular: true })
+    }
+}
+
+const LAYERED_MAX_DEPTH: usize = 10;
+
+fn max_component(v: Vector3) -> f64 {
+    v.x.max(v.y.max(v.z))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Layer {
+    Coat,
+    Base,
+}
+
+// dielectric coat over arbitrary base material like car paint or varnished wood.
+// light bouncing between the layers is evaluated by stochastic random walk of Guo et al.
+// and the coat absorbs light by tint for each unit of thickness along normal.
+pub struct LayeredMaterial {
+    base: Arc<PMaterial>,
+    ior: Arc<PTexture<f64>>,
+    roughness: Arc<PTexture<f64>>,
+    tint: Arc<PTexture<Vector3>>,
+    thickness: Arc<PTexture<f64>>,
+}
+
+impl LayeredMaterial {
+    pub fn new(base: Arc<PMaterial>, ior: f64, roughness: f64) -> LayeredMaterial {
+        LayeredMaterial::with_absorption(base, ior, roughness, Vector3::one(), 0.0)
+    }
+    pub fn with_absorption(base: Arc<PMaterial>, ior: f64, roughness: f64, tint: Vector3, thickness: f64) -> LayeredMaterial {
+        LayeredMaterial::textured(base, constant(ior), constant(roughness), constant(tint), constant(thickness))
+    }
+    pub fn textured(base: Arc<PMaterial>, ior: Arc<PTexture<f64>>, roughness: Arc<PTexture<f64>>,
+        tint: Arc<PTexture<Vector3>>, thickness: Arc<PTexture<f64>>) -> LayeredMaterial {
+        LayeredMaterial { base, ior, roughness, tint, thickness }
+    }
+    fn stack(&self, isec: &Intersection) -> LayerStack<'_> {
+        let roughness = self.roughness.value(isec);
+        LayerStack {
+            base: &*self.base,
+            base_frame: self.base.shade(isec),
+            coat: Coat { ior: self.ior.value(isec), roughness: (roughness, roughness) },
+            tint: self.tint.value(isec),
+            thickness: self.thickness.value(isec),
+        }
+    }
+}
+
+// layers with parameters at intersection
+struct LayerStack<'a> {
+    base: &'a PMaterial,
+    // intersection shaded by base material, whose frame is set up with outgoing direction above the coat
+    base_frame: Intersection,
+    coat: Coat,
+    tint: Vector3,
+    thickness: f64,
+}
+
+impl<'a> LayerStack<'a> {
+    fn transmittance(&self, w: Vector3) -> Vector3 {
+        if self.thickness == 0.0 {
+            return Vector3::one();
+        }
+        let d = self.thickness / w.z.abs();
+        vec3(self.tint.x.powf(d), self.tint.y.powf(d), self.tint.z.powf(d))
+    }
+    // intersection seen by base material with outgoing direction in local space
+    fn base_isec(&self, isec: &Intersection, wo: Vector3) -> Intersection {
+        Intersection { wo: isec.to_world(wo), ..self.base_frame }
+    }
+    fn layer_bsdf(&self, isec: &Intersection, layer: Layer, wo: Vector3, wi: Vector3) -> Vector3 {
+        match layer {
+            Layer::Coat => Vector3::one() * self.coat.bsdf(wo, wi),
+            Layer::Base => self.base.bsdf(&self.base_isec(isec, wo), isec.to_world(wi)),
+        }
+    }
+    fn layer_pdf(&self, isec: &Intersection, layer: Layer, wo: Vector3, wi: Vector3, lobe: Lobe) -> f64 {
+        match layer {
+            Layer::Coat => self.coat.pdf(wo, wi, lobe),
+            Layer::Base => {
+                let reflection = wo.z * wi.z > 0.0;
+                if (lobe == Lobe::Reflection && !reflection) || (lobe == Lobe::Transmission && reflection) {
+                    return 0.0;
+                }
+                self.base.pdf(&self.base_isec(isec, wo), isec.to_world(wi))
+            }
+        }
+    }
+    fn layer_sample(&self, isec: &Intersection, layer: Layer, wo: Vector3, lobe: Lobe) -> Option<LayerSample> {
+        match layer {
+            Layer::Coat => self.coat.sample(wo, lobe),
+            Layer::Base => {
+                // base material can not restrict lobes, so samples of the other lobe are discarded
+                let base_isec = self.base_isec(isec, wo);
+                let bsdf = self.base.sample(&base_isec)?;
+                let wi = isec.to_local(bsdf.wi);
+                let reflection = wo.z * wi.z > 0.0;
+                if bsdf.pdf == 0.0 || (lobe == Lobe::Reflection && !reflection) || (lobe == Lobe::Transmission && reflection) {
+                    return None;
+                }
+                Some(LayerSample { wi, value: bsdf.value, pdf: bsdf.pdf, specular: bsdf.specular })
+            }
+        }
+    }
+    // wo is in upper hemisphere
+    fn eval(&self, isec: &Intersection, wo: Vector3, wi: Vector3) -> Vector3 {
+        let exit_coat = wi.z > 0.0;
+        let (exit, non_exit) = if exit_coat { (Layer::Coat, Layer::Base) } else { (Layer::Base, Layer::Coat) };
+        let mut value = if exit_coat { Vector3::one() * self.coat.bsdf(wo, wi) } else { Vector3::zero() };
+
+        let wos = match self.coat.sample(wo, Lobe::Transmission) {
+            Some(s) => s,
+            None => return value,
+        };
+        // direction sampled from wi at exit layer for next event estimation.
+        // light goes through exit layer in reverse direction of sampling, and
+        // bsdf of specular coat in reverse direction is scaled by square of index of refraction.
+        let nee = self.layer_sample(isec, exit, wi, Lobe::Transmission).map(|wis| {
+            let value = if !wis.specular {
+                self.layer_bsdf(isec, exit, wis.wi, wi)
+            } else if exit == Layer::Coat {
+                wis.value * math::pow2(self.coat.ior)
+            } else {
+                wis.value
+            };
+            LayerSample { value, ..wis }
+        });
+        let exit_specular = exit == Layer::Coat && self.coat.is_specular();
+
+        let mut beta = wos.value * wos.wi.z.abs() / wos.pdf;
+        if !exit_coat {
+            // direct transmission through both layers
+            let f = self.layer_bsdf(isec, Layer::Base, -wos.wi, wi);
+            if max_component(f) > 0.0 {
+                let weight = if wos.specular {
+                    1.0
+                } else {
+                    math::power_heuristic(wos.pdf, self.layer_pdf(isec, Layer::Base, wi, -wos.wi, Lobe::Transmission))
+                };
+                value += beta * self.transmittance(wos.wi) * f * weight;
+            }
+            if let Some(wis) = &nee {
+                let f = self.coat.bsdf(wo, -wis.wi);
+                if f > 0.0 {
+                    let weight = if wis.specular {
+                        1.0
+                    } else {
+                        math::power_heuristic(wis.pdf, self.coat.pdf(wo, -wis.wi, Lobe::Transmission))
+                    };
+                    value += f * wis.wi.z.abs() * weight * self.transmittance(wis.wi) * wis.value / wis.pdf;
+                }
+            }
+        }
+        let mut w = wos.wi;
+        let mut at_coat = true;
+        for depth in 0..LAYERED_MAX_DEPTH {
+            if depth > 3 {
+                let p = max_component(beta);
+                if p < 0.25 {
+                    let q = (1.0 - p).max(0.0);
+                    if math::random() < q {
+                        break;
+                    }
+                    beta /= 1.0 - q;
+                }
+            }
+            at_coat = !at_coat;
+            beta *= self.transmittance(w);
+            let layer = if at_coat { Layer::Coat } else { Layer::Base };
+
+            if layer == exit {
+                let bs = match self.layer_sample(isec, exit, -w, Lobe::Reflection) {
+                    Some(s) => s,
+                    None => break,
+                };
+                beta *= bs.value * bs.wi.z.abs() / bs.pdf;
+                w = bs.wi;
+                continue;
+            }
+
+            if let Some(wis) = &nee {
+                let f = self.layer_bsdf(isec, non_exit, -w, -wis.wi);
+                if max_component(f) > 0.0 {
+                    let weight = if wis.specular {
+                        1.0
+                    } else {
+                        math::power_heuristic(wis.pdf, self.layer_pdf(isec, non_exit, -w, -wis.wi, Lobe::Reflection))
+                    };
+                    value += beta * f * wis.wi.z.abs() * weight * self.transmittance(wis.wi) * wis.value / wis.pdf;
+                }
+            }
+
+            let bs = match self.layer_sample(isec, non_exit, -w, Lobe::Reflection) {
+                Some(s) => s,
+                None => break,
+            };
+            beta *= bs.value * bs.wi.z.abs() / bs.pdf;
+            w = bs.wi;
+
+            if !exit_specular {
+                let f_exit = self.layer_bsdf(isec, exit, -w, wi);
+                if max_component(f_exit) > 0.0 {
+                    let weight = if bs.specular {
+                        1.0
+                    } else {
+                        // pdf of next event estimation which samples the same direction from wi
+                        math::power_heuristic(bs.pdf, self.layer_pdf(isec, exit, wi, -w, Lobe::Transmission))
+                    };
+                    value += beta * self.transmittance(bs.wi) * f_exit * weight;
+                }
+            }
+        }
+        value
+    }
+    // returned pdf is not density of wi but of the whole random walk, which is only proportional to it
+    // the walk is specular only if every scattering in it is specular
+    fn sample_walk(&self, isec: &Intersection, wo: Vector3) -> Option<LayerSample> {
+        let bs = self.coat.sample(wo, Lobe::All)?;
+        if bs.wi.z > 0.0 {
+            return Some(bs);
+        }
+        let mut value = bs.value * bs.wi.z.abs();
+        let mut pdf = bs.pdf;
+        let mut w = bs.wi;
+        let mut specular = bs.specular;
+        let mut at_coat = true;
+        for depth in 0..LAYERED_MAX_DEPTH {
+            let p = max_component(value) / pdf;
+            if depth > 3 && p < 0.25 {
+                let q = (1.0 - p).max(0.0);
+                if math::random() < q {
+                    return None;
+                }
+                pdf *= 1.0 - q;
+            }
+            at_coat = !at_coat;
+            value *= self.transmittance(w);
+            let layer = if at_coat { Layer::Coat } else { Layer::Base };
+            let bs = self.layer_sample(isec, layer, -w, Lobe::All)?;
+            value *= bs.value;
+            pdf *= bs.pdf;
+            w = bs.wi;
+            specular &= bs.specular;
+            // transmission through coat upward or base downward leaves the layers
+            if (w.z > 0.0) == at_coat {
+                return Some(LayerSample { wi: w, value, pdf, specular });
+            }
+            value *= w.z.abs();
+        }
+        None
+    }
+    // approximation of pdf by single scattering at base, mixed with uniform distribution
+    fn approximate_pdf(&self, isec: &Intersection, wo: Vector3, wi: Vector3) -> f64 {
+        let transmission = 1.0 - math::fresnel_dielectric(wo.z, self.coat.ior);
+        let wos = match self.coat.sample(wo, Lobe::Transmission) {
+            Some(s) => s,
+            None => return 0.0,
+        };
+        let pdf = if wi.z < 0.0 {
+            transmission * self.layer_pdf(isec, Layer::Base, -wos.wi, wi, Lobe::Transmission)
+        } else if self.coat.is_specular() {
+            match self.coat.sample(wi, Lobe::Transmission) {
+                Some(wis) => {
+                    // change of solid angle by refraction
+                    let jacobian = wi.z / (math::pow2(self.coat.ior) * wis.wi.z.abs());
+                    let base_pdf = self.layer_pdf(isec, Layer::Base, -wos.wi, -wis.wi, Lobe::Reflection);
+                    transmission * base_pdf * jacobian
+                }
+                None => 0.0,
+            }
+        } else {
+            let exit_pdf = self.layer_sample(isec, Layer::Base, -wos.wi, Lobe::Reflection)
+                .map_or(0.0, |rs| self.coat.pdf(-rs.wi, wi, Lobe::Transmission));
+            self.coat.pdf(wo, wi, Lobe::All) + transmission * exit_pdf
+        };
+        0.1 / (4.0 * math::PI) + 0.9 * pdf
+    }
+}
+
+// bsdf() is an unbiased estimate by random walk and value of sample() is weighted by the walk itself.
+// pdf() is an approximation which doesn't match the walk, so it is only valid for weighting of
+// multiple importance sampling.
+impl Material for LayeredMaterial {
+    fn bsdf(&self, isec: &Intersection, wi: Vector3) -> Vector3 {
+        // both sides of surface are coated
+        let wo = isec.to_local(isec.wo);
+        let wi = isec.to_local(wi);
+        if wo.z == 0.0 {
+            return Vector3::zero();
+        }
+        self.stack(isec).eval(isec, upper(wo, wo), upper(wo, wi))
+    }
+    fn sample(&self, isec: &Intersection) -> Option<Bsdf> {
+        let wo = isec.to_local(isec.wo);
+        if wo.z == 0.0 {
+            return None;
+        }
+        let ls = self.stack(isec).sample_walk(isec, upper(wo, wo))?;
+        if ls.pdf == 0.0 {
+            return None;
+        }
+        Some(Bsdf { value: ls.value, wi: isec.to_world(upper(wo, ls.wi)), pdf: ls.pdf, specular: ls.specular })
+    }
+    fn pdf(&self, isec: &Intersection, wi: Vector3) -> f64 {
+        let wo = isec.to_local(isec.wo);
+        let wi = isec.to_local(wi);
+        if wo.z == 0.0 {
+            return 0.0;
+        }
+        self.stack(isec).approximate_pdf(isec, upper(wo, wo), upper(wo, wi))
+    }
+    fn stochastic(&self) -> bool {
+        true
+    }
+}
+
+// fabric sheen of Charlie distribution by Estevez and Kulla with visibility term by Neubelt and Pettineo.
+// optional base material below the sheen is attenuated by albedo of the sheen.
+pub struct SheenMaterial {
+    color: Arc<PTexture<Vector3>>,
+    roughness: Arc<PTexture<f64>>,
+    base: Option<Arc<PMaterial>>,
+}
+
+impl SheenMaterial {
+    pub fn new(color: Vector3, roughness: f64) -> SheenMaterial {
+        SheenMaterial::textured(constant(color), constant(roughness), None)
+    }
+    pub fn layered(color: Vector3, roughness: f64, base: Arc<PMaterial>) -> SheenMaterial {
+        SheenMaterial::textured(constant(color), constant(roughness), Some(base))
+    }
+    pub fn textured(color: Arc<PTexture<Vector3>>, roughness: Arc<PTexture<f64>>, base: Option<Arc<PMaterial>>) -> SheenMaterial {
+        SheenMaterial { color, roughness, base }
+    }
+    // scale of base material not reflected by the sheen
+    fn base_scale(&self, isec: &Intersection, wo: Vector3) -> f64 {
+        let albedo = sheen_albedo_table().albedo(wo.z, self.roughness.value(isec));
+        1.0 - max_component(self.color.value(isec)) * albedo
+    }
+}
+
+// directional albedo of the sheen with unit color
+fn sheen_albedo_table() -> &'static AlbedoTable {
+    static TABLE: OnceLock<AlbedoTable> = OnceLock::new();
+    TABLE.get_or_init(|| AlbedoTable::new(|wo, roughness, u1, u2| {
+        let r = (1.0 - u1 * u1).max(0.0).sqrt();
+        let phi = math::TWO_PI * u2;
+        let wi = vec3(r * phi.cos(), r * phi.sin(), u1);
+        // uniform sampling of hemisphere
+        sheen_reflection(wo, wi, roughness) * wi.z * math::TWO_PI
+    }))
+}
+
+fn sheen_reflection(wo: Vector3, wi: Vector3, roughness: f64) -> f64 {
+    if wo.z <= 0.0 || wi.z <= 0.0 {
+        return 0.0;
+    }
+    let roughness = math::clamp(roughness, 1e-2, 1.0);
+    let wh = (wo + wi).norm();
+    let inv = 1.0 / roughness;
+    let sin2 = (1.0 - wh.z * wh.z).max(0.0);
+    let d = (2.0 + inv) * sin2.powf(0.5 * inv) / math::TWO_PI;
+    let v = 1.0 / (4.0 * (wi.z + wo.z - wi.z * wo.z));
+    d * v
+}
+
+impl Material for SheenMaterial {
+    fn bsdf(&self, isec: &Intersection, wi: Vector3) -> Vector3 {
+        let wo = isec.to_local(isec.wo);
+        if wo.z <= 0.0 {
+            return Vector3::zero();
+        }
+        let sheen = self.color.value(isec) * sheen_reflection(wo, isec.to_local(wi), self.roughness.value(isec));
+        match &self.base {
+            Some(base) => sheen + self.base_scale(isec, wo) * base.bsdf(&base.shade(isec), wi),
+            None => sheen,
+        }
+    }
+    fn sample(&self, isec: &Intersection) -> Option<Bsdf> {
+        let wo = isec.to_local(isec.wo);
+        if wo.z <= 0.0 {
+            return None;
+        }
+        let stochastic = self.stochastic();
+        let wi = match &self.base {
+            // choose sheen or base with equal probability
+            Some(base) if math::random() < 0.5 => {
+                let base_isec = base.shade(isec);
+                let bsdf = base.sample(&base_isec)?;
+                if bsdf.specular || stochastic || base.pdf(&base_isec, bsdf.wi) == 0.0 {
+                    let value = self.base_scale(isec, wo) * bsdf.value;
+                    return Some(Bsdf { value, pdf: 0.5 * bsdf.pdf, ..bsdf });
+                }
+                bsdf.wi
+            }
+            _ => isec.to_world(math::sample_random_hemisphere_dir().0),
+        };
+        if stochastic {
+            // sample of stochastic base is only valid with its own pdf, so sheen is estimated alone as well
+            let value = self.color.value(isec) * sheen_reflection(wo, isec.to_local(wi), self.roughness.value(isec));
+            return Some(Bsdf { value, wi, pdf: 0.5 / math::TWO_PI, specular: false });
+        }
+        let value = self.bsdf(isec, wi);
+        let pdf = self.pdf(isec, wi);
+        Some(Bsdf { value, wi, pdf, specular: false })
+    }
+    fn pdf(&self, isec: &Intersection, wi: Vector3) -> f64 {
+        if isec.normal.dot(isec.wo) <= 0.0 {
+            return 0.0;
+        }
+        let pdf = if isec.normal.dot(wi) > 0.0 { 1.0 / math::TWO_PI } else { 0.0 };
+        match &self.base {
+            Some(base) => 0.5 * (pdf + base.pdf(&base.shade(isec), wi)),
+            None => pdf,
+        }
+    }
+    fn stochastic(&self) -> bool {
+        self.base.as_ref().is_some_and(|base| base.stochastic())
+    }
+}
+
+// stochastic mix of two materials. weight is fraction of the second material.
+pub struct MixMaterial {
+    first: Arc<PMaterial>,
+    second: Arc<PMaterial>,
+    weight: Arc<PTexture<f64>>,
+}
+
+impl MixMaterial {
+    pub fn new(first: Arc<PMaterial>, second: Arc<PMaterial>, weight: f64) -> MixMaterial {
+        MixMaterial::textured(first, second, constant(weight))
+    }
+    pub fn textured(first: Arc<PMaterial>, second: Arc<PMaterial>, weight: Arc<PTexture<f64>>) -> MixMaterial {
+        MixMaterial { first, second, weight }
+    }
+    fn weight(&self, isec: &Intersection) -> f64 {
+        math::clamp(self.weight.value(isec), 0.0, 1.0)
+    }
+}
+
+impl Material for MixMaterial {
+    fn bsdf(&self, isec: &Intersection, wi: Vector3) -> Vector3 {
+        let w = self.weight(isec);
+        (1.0 - w) * self.first.bsdf(&self.first.shade(isec), wi) + w * self.second.bsdf(&self.second.shade(isec), wi)
+    }
+    fn sample(&self, isec: &Intersection) -> Option<Bsdf> {
+        let w = self.weight(isec);
+        let (material, probability) = if math::random() < w {
+            (&self.second, w)
+        } else {
+            (&self.first, 1.0 - w)
+        };
+        let material_isec = material.shade(isec);
+        let bsdf = material.sample(&material_isec)?;
+        // specular direction can not be sampled by the other material, and sample of stochastic material
+        // is only valid with its own pdf. then the chosen material alone is estimated
+        if bsdf.specular || self.stochastic() || material.pdf(&material_isec, bsdf.wi) == 0.0 {
+            return Some(Bsdf { value: probability * bsdf.value, pdf: probability * bsdf.pdf, ..bsdf });
+        }
+        let value = self.bsdf(isec, bsdf.wi);
+        let pdf = self.pdf(isec, bsdf.wi);
+        Some(Bsdf { value, wi: bsdf.wi, pdf, specular: false })
+    }
+    fn pdf(&self, isec: &Intersection, wi: Vector3) -> f64 {
+        let w = self.weight(isec);
+        (1.0 - w) * self.first.pdf(&self.first.shade(isec), wi) + w * self.second.pdf(&self.second.shade(isec), wi)
+    }
+    fn stochastic(&self) -> bool {
+        self.first.stochastic() || self.second.stochastic()
+    }
+    fn emit(&self, isec: &Intersection) -> Vector3 {
+        let w = self.weight(isec);
+        (1.0 - w) * self.first.emit(&self.first.shade(isec)) + w * self.second.emit(&self.second.shade(isec))
+    }
+}
+
+// step in texture coordinates for finite difference of bump map without ray differential
+const BUMP_DELTA: f64 = 1e-3;
+
+enum Perturbation {
+    // tangent space normal stored in [0, 1], which should be loaded by ImageTexture::linear
+    Normal(Arc<PTexture<Vector3>>),
+    // height along normal in texture value multiplied by the scale
+    Bump(Arc<PTexture<f64>>, f64),
+}
+
+// perturbs shading normal of base material by normal map or bump map.
+// perturbation of base material, if any, is applied over this one
+pub struct NormalMapMaterial {
+    base: Arc<PMaterial>,
+    perturbation: Perturbation,
+}
+
+impl NormalMapMaterial {
+    pub fn new(base: Arc<PMaterial>, normal_map: Arc<PTexture<Vector3>>) -> NormalMapMaterial {
+        NormalMapMaterial { base, perturbation: Perturbation::Normal(normal_map) }
+    }
+    pub fn bump(base: Arc<PMaterial>, height: Arc<PTexture<f64>>, scale: f64) -> NormalMapMaterial {
+        NormalMapMaterial { base, perturbation: Perturbation::Bump(height, scale) }
+    }
+    fn perturbed_normal(&self, isec: &Intersection) -> Vector3 {
+        match &self.perturbation {
+            Perturbation::Normal(normal_map) => {
+                let n = 2.0 * normal_map.value(isec) - 1.0;
+                isec.to_world(n).norm()
+            },
+            Perturbation::Bump(height, scale) => {
+                // position shifted along the parameterization in both world space and space of shape
+                let shift = |du: f64, dv: f64| {
+                    Intersection {
+                        pos: isec.pos + du * isec.dpdu + dv * isec.dpdv,
+                        object_pos: isec.object_pos + du * isec.object_dpdu + dv * isec.object_dpdv,
+                        uv: (isec.uv.0 + du, isec.uv.1 + dv),
+                        ..*isec
+                    }
+                };
+                // step is half of pixel footprint if it is known
+                let (du, dv) = isec.differential.map_or((0.0, 0.0), |d| {
+                    (0.5 * (d.duvdx.0.abs() + d.duvdy.0.abs()), 0.5 * (d.duvdx.1.abs() + d.duvdy.1.abs()))
+                });
+                let du = if du > 0.0 { du } else { BUMP_DELTA };
+                let dv = if dv > 0.0 { dv } else { BUMP_DELTA };
+                let h = height.value(isec);
+                let dhdu = (height.value(&shift(du, 0.0)) - h) / du;
+                let dhdv = (height.value(&shift(0.0, dv)) - h) / dv;
+                // change of normal over the surface is ignored
+                let dpdu = isec.dpdu + scale * dhdu * isec.normal;
+                let dpdv = isec.dpdv + scale * dhdv * isec.normal;
+                let n = dpdu.cross(dpdv);
+                if n.sq_mag() == 0.0 {
+                    return isec.normal;
+                }
+                let n = n.norm();
+                if n.dot(isec.normal) < 0.0 { -n } else { n }
+            },
+        }
+    }
+    // light leaks if shading and actual surfaces disagree whether light is reflected or transmitted
+    fn is_consistent(&self, isec: &Intersection, wi: Vector3) -> bool {
+        let shading = isec.normal.dot(isec.wo) * isec.normal.dot(wi);
+        let geometric = isec.geometric_normal.dot(isec.wo) * isec.geometric_normal.dot(wi);
+        shading * geometric > 0.0
+    }
+}
+
+impl Material for NormalMapMaterial {
+    fn bsdf(&self, isec: &Intersection, wi: Vector3) -> Vector3 {
+        if self.is_consistent(isec, wi) {
+            self.base.bsdf(&self.base.shade(isec), wi)
+        } else {
+            Vector3::zero()
+        }
+    }
+    fn sample(&self, isec: &Intersection) -> Option<Bsdf> {
+        self.base.sample(&self.base.shade(isec)).filter(|bsdf| self.is_consistent(isec, bsdf.wi))
+    }
+    fn pdf(&self, isec: &Intersection, wi: Vector3) -> f64 {
+        if self.is_consistent(isec, wi) {
+            self.base.pdf(&self.base.shade(isec), wi)
+        } else {
+            0.0
+        }
+    }
+    fn stochastic(&self) -> bool {
+        self.base.stochastic()
+    }
+    fn emit(&self, isec: &Intersection) -> Vector3 {
+        self.base.emit(&self.base.shade(isec))
+    }
+    fn shade(&self, isec: &Intersection) -> Intersection {
+        let mut normal = self.perturbed_normal(isec);
+        // bend normal so that outgoing direction stays on the same side as the actual surface
+        let side = isec.geometric_normal.dot(isec.wo);
+        if normal.dot(isec.wo) * side <= 0.0 {
+            normal = (normal + (0.01 * side.signum() - normal.dot(isec.wo)) * isec.wo).norm();
+        }
+        let tangent = math::tangent(normal, isec.tangent);
+        Intersection { normal, tangent, ..*isec }
     }
 }
 
 pub struct IlluminantMaterial {
-    emission: Vector3,
+    emission: Arc<PTexture<Vector3>>,
+    intensity: f64,
+    two_sided: bool,
 }
 
 impl IlluminantMaterial {
     pub fn new(emission: Vector3) -> IlluminantMaterial {
-        IlluminantMaterial { emission }
+        IlluminantMaterial::textured(constant(emission), 1.0, false)
+    }
+    // emission color is given by texture and scaled by intensity
+    pub fn textured(emission: Arc<PTexture<Vector3>>, intensity: f64, two_sided: bool) -> IlluminantMaterial {
+        IlluminantMaterial { emission, intensity, two_sided }
+    }
+    // color is normalized to unit luminance and power is distributed over area of emitter
+    pub fn with_unit(color: Vector3, unit: LightUnit, area: f64, two_sided: bool) -> IlluminantMaterial {
+        let area = if two_sided { 2.0 * area } else { area };
+        IlluminantMaterial::textured(constant(color::normalize_luminance(color)), unit.radiance(area), two_sided)
     }
 }
 
 impl Material for IlluminantMaterial {
-    fn bsdf(&self, isec: &Intersection, wi: Vector3) -> Vector3 {
+    fn bsdf(&self, _isec: &Intersection, _wi: Vector3) -> Vector3 {
         vec3(0.0, 0.0, 0.0)
     }
-    fn sample(&self, isec: &Intersection) -> Option<Bsdf> {
+    fn sample(&self, _isec: &Intersection) -> Option<Bsdf> {
         None
     }
     fn emit(&self, isec: &Intersection) -> Vector3 {
-        if isec.normal.dot(isec.wo) > 0.0 {
-           self.emission
+        if self.two_sided || isec.normal.dot(isec.wo) > 0.0 {
+            self.intensity * self.emission.value(isec)
         } else {
             Vector3::zero()
         }
@@ -1,4 +1,7 @@
-use rand;
+use std::cell::RefCell;
+
+use rand::{ Rng, SeedableRng, FromEntropy };
+use rand::rngs::SmallRng;
 
 use crate::{ vec3, Vector3 };
 
@@ -9,6 +12,20 @@ pub const INV_PI: f64 = 1.0 / PI;
 pub const GAMMA: f64 = 2.2;
 pub const INV_GAMMA: f64 = 1.0 / GAMMA;
 
+thread_local! {
+    static RNG: RefCell<SmallRng> = RefCell::new(SmallRng::from_entropy());
+}
+
+// uniform random number in [0, 1) from generator of current thread
+pub fn random() -> f64 {
+    RNG.with(|rng| rng.borrow_mut().gen::<f64>())
+}
+
+// restarts generator of current thread to reproduce the same random numbers
+pub fn seed(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = SmallRng::seed_from_u64(seed));
+}
+
 pub fn reflect(v: Vector3, n: Vector3) -> Vector3 {
     v - 2.0 * v.dot(n) * n
 }
@@ -46,6 +63,41 @@ pub fn schlick_fresnel(cosine: f64, ri: f64) -> f64 {
     r0 + (1.0 - r0) * pow5(1.0 - cosine)
 }
 
+// exact fresnel reflectance of dielectric, eta is relative index of refraction
+pub fn fresnel_dielectric(cosine: f64, eta: f64) -> f64 {
+    let (cosine, eta) = if cosine < 0.0 { (-cosine, 1.0 / eta) } else { (cosine, eta) };
+    let sin2_t = (1.0 - cosine * cosine) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let r_parl = (eta * cosine - cos_t) / (eta * cosine + cos_t);
+    let r_perp = (cosine - eta * cos_t) / (cosine + eta * cos_t);
+    0.5 * (r_parl * r_parl + r_perp * r_perp)
+}
+
+// exact fresnel reflectance of conductor with complex index of refraction eta + ik
+pub fn fresnel_conductor(cosine: f64, eta: f64, k: f64) -> f64 {
+    let cos2 = cosine * cosine;
+    let sin2 = 1.0 - cos2;
+    let t0 = eta * eta - k * k - sin2;
+    let a2b2 = (t0 * t0 + 4.0 * eta * eta * k * k).sqrt();
+    let t1 = a2b2 + cos2;
+    let a = (0.5 * (a2b2 + t0)).max(0.0).sqrt();
+    let t2 = 2.0 * cosine * a;
+    let rs = (t1 - t2) / (t1 + t2);
+    let t3 = cos2 * a2b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+    0.5 * (rp + rs)
+}
+
+// weight of multiple importance sampling with beta = 2
+pub fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b > 0.0 { a / (a + b) } else { 0.0 }
+}
+
 pub fn clamp(v: f64, min: f64, max: f64) -> f64 {
     v.min(max).max(min)
 }
@@ -71,8 +123,8 @@ pub fn solve_quadratic_equation(a: f64, b: f64, c: f64) -> Option<(f64, f64)> {
 }
 
 pub fn sample_random_cosine_dir() -> (Vector3, f64) {
-    let r1 = rand::random::<f64>();
-    let r2 = rand::random::<f64>();
+    let r1 = random();
+    let r2 = random();
     let z = (1.0 - r2).sqrt();
     let phi = 2.0 * PI * r1;
     let x = phi.cos() * r2.sqrt();
@@ -82,7 +134,16 @@ pub fn sample_random_cosine_dir() -> (Vector3, f64) {
     (dir, pdf)
 }
 
-pub fn change_basis(v: Vector3, n: Vector3) -> Vector3{
+pub fn sample_random_hemisphere_dir() -> (Vector3, f64) {
+    let z = random();
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * random();
+    let dir = vec3(r * phi.cos(), r * phi.sin(), z);
+    let pdf = 1.0 / (2.0 * PI);
+    (dir, pdf)
+}
+
+fn basis(n: Vector3) -> (Vector3, Vector3, Vector3) {
     let n = n.norm();
     let up = if n.x.abs() > 0.9 {
         vec3(0.0, 1.0, 0.0)
@@ -91,10 +152,25 @@ pub fn change_basis(v: Vector3, n: Vector3) -> Vector3{
     };
     let s = n.cross(up).norm();
     let t = s.cross(n);
+    (s, t, n)
+}
 
+pub fn change_basis(v: Vector3, n: Vector3) -> Vector3{
+    let (s, t, n) = basis(n);
     v.x * s + v.y * t + v.z * n
 }
 
+// unit vector perpendicular to n which is closest to v
+pub fn tangent(n: Vector3, v: Vector3) -> Vector3 {
+    let t = v - n * n.dot(v);
+    if t.sq_mag() > 1e-12 {
+        t.norm()
+    } else {
+        basis(n).0
+    }
+}
+
+
 pub fn sphere_uv(v: Vector3) -> (f64, f64) {
     let phi = v.z.atan2(v.x);
     let theta = v.y.asin();
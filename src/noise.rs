@@ -0,0 +1,108 @@
+use crate::{ vec3, Vector3 };
+
+// integer hash of lattice point, which replaces permutation table of perlin noise
+fn hash(x: i64, y: i64, z: i64) -> u32 {
+    let mut h = (x as u32).wrapping_mul(0x8da6_b343)
+        ^ (y as u32).wrapping_mul(0xd816_3841)
+        ^ (z as u32).wrapping_mul(0xcb1a_b31f);
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x7feb_352d);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x846c_a68b);
+    h ^ (h >> 16)
+}
+
+// dot product with one of 12 gradients toward edges of cube
+fn grad(h: u32, x: f64, y: f64, z: f64) -> f64 {
+    match h % 12 {
+        0 => x + y,
+        1 => -x + y,
+        2 => x - y,
+        3 => -x - y,
+        4 => x + z,
+        5 => -x + z,
+        6 => x - z,
+        7 => -x - z,
+        8 => y + z,
+        9 => -y + z,
+        10 => y - z,
+        _ => -y - z,
+    }
+}
+
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(t: f64, a: f64, b: f64) -> f64 {
+    a + t * (b - a)
+}
+
+// improved perlin noise in about [-1, 1], which is zero at lattice points
+pub fn perlin(p: Vector3) -> f64 {
+    let (fx, fy, fz) = (p.x.floor(), p.y.floor(), p.z.floor());
+    let (ix, iy, iz) = (fx as i64, fy as i64, fz as i64);
+    let (x, y, z) = (p.x - fx, p.y - fy, p.z - fz);
+    let (u, v, w) = (fade(x), fade(y), fade(z));
+    let corner = |dx: i64, dy: i64, dz: i64| {
+        grad(hash(ix + dx, iy + dy, iz + dz), x - dx as f64, y - dy as f64, z - dz as f64)
+    };
+    lerp(w,
+        lerp(v, lerp(u, corner(0, 0, 0), corner(1, 0, 0)), lerp(u, corner(0, 1, 0), corner(1, 1, 0))),
+        lerp(v, lerp(u, corner(0, 0, 1), corner(1, 0, 1)), lerp(u, corner(0, 1, 1), corner(1, 1, 1))))
+}
+
+// fractional brownian motion which sums octaves of halving amplitude and doubling frequency.
+// it is normalized by total amplitude to keep the range of perlin noise
+pub fn fbm(p: Vector3, octaves: u32) -> f64 {
+    let (mut sum, mut total, mut amplitude, mut p) = (0.0, 0.0, 1.0, p);
+    for _ in 0..octaves.max(1) {
+        sum += amplitude * perlin(p);
+        total += amplitude;
+        amplitude *= 0.5;
+        p = 2.0 * p;
+    }
+    sum / total
+}
+
+// fbm of absolute value in [0, 1], which has sharp creases at zero crossings
+pub fn turbulence(p: Vector3, octaves: u32) -> f64 {
+    let (mut sum, mut total, mut amplitude, mut p) = (0.0, 0.0, 1.0, p);
+    for _ in 0..octaves.max(1) {
+        sum += amplitude * perlin(p).abs();
+        total += amplitude;
+        amplitude *= 0.5;
+        p = 2.0 * p;
+    }
+    (sum / total).min(1.0)
+}
+
+// distances to the closest and second closest feature points of cellular noise.
+// each unit cell has one feature point at random position
+pub fn worley(p: Vector3) -> (f64, f64) {
+    let (fx, fy, fz) = (p.x.floor(), p.y.floor(), p.z.floor());
+    let (ix, iy, iz) = (fx as i64, fy as i64, fz as i64);
+    let (mut f1, mut f2) = (f64::INFINITY, f64::INFINITY);
+    for dz in -1..=1 {
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                let (cx, cy, cz) = (ix + dx, iy + dy, iz + dz);
+                let h = hash(cx, cy, cz);
+                let offset = vec3(
+                    (h & 0x3ff) as f64 / 1024.0,
+                    ((h >> 10) & 0x3ff) as f64 / 1024.0,
+                    ((h >> 20) & 0x3ff) as f64 / 1024.0,
+                );
+                let feature = vec3(cx as f64, cy as f64, cz as f64) + offset;
+                let d = (feature - p).mag();
+                if d < f1 {
+                    f2 = f1;
+                    f1 = d;
+                } else if d < f2 {
+                    f2 = d;
+                }
+            }
+        }
+    }
+    (f1, f2)
+}
@@ -1,6 +1,10 @@
+use std::collections::HashMap;
+
 use crate::{ vec3, Vector3 };
 use crate::math;
 use crate::{ Ray, Intersection, Aabb };
+use crate::PTexture;
+use crate::Transform;
 
 pub trait Shape {
     fn hit(&self, ray: &Ray, tmin: f64, tmax: f64) -> Option<Intersection>;
@@ -8,6 +12,19 @@ pub trait Shape {
     fn sample(&self) -> (Vector3, f64) {
         panic!("sample method has not implemented");
     }
+    fn area(&self) -> f64 {
+        panic!("area method has not implemented");
+    }
+    // area of shape after the transform
+    fn transformed_area(&self, _transform: &Transform) -> f64 {
+        panic!("transformed_area method has not implemented");
+    }
+}
+
+// ratio of transformed area to original area of surface element with the normal
+fn area_scale(transform: &Transform, normal: Vector3) -> f64 {
+    let (x, y, z) = (transform.vector(vec3(1.0, 0.0, 0.0)), transform.vector(vec3(0.0, 1.0, 0.0)), transform.vector(vec3(0.0, 0.0, 1.0)));
+    x.dot(y.cross(z)).abs() * transform.normal(normal).mag()
 }
 
 pub type PShape = dyn Shape + Sync + 'static;
@@ -23,6 +40,21 @@ impl Sphere {
         let aabb = Aabb::new(center - radius, center + radius);
         Sphere { center, radius, aabb }
     }
+    fn intersection(&self, ray: &Ray, t: f64) -> Intersection {
+        let pos = ray.at(t);
+        let normal = (pos - self.center).norm();
+        // derivatives of spherical coordinates mapped by math::sphere_uv
+        let d = self.radius * normal;
+        let dpdu = math::TWO_PI * vec3(d.z, 0.0, -d.x);
+        let rho = (d.x * d.x + d.z * d.z).sqrt();
+        let dpdv = if rho > 0.0 {
+            math::PI * vec3(-d.y * d.x / rho, rho, -d.y * d.z / rho)
+        } else {
+            // pole where longitude is undefined
+            math::PI * self.radius * vec3(-d.y.signum(), 0.0, 0.0)
+        };
+        Intersection::new(t, -ray.dir.norm(), pos, normal, math::sphere_uv(normal), dpdu, dpdv)
+    }
 }
 
 impl Shape for Sphere {
@@ -33,21 +65,9 @@ impl Shape for Sphere {
         let c = oc.dot(oc) - self.radius * self.radius;
         math::solve_quadratic_equation(a, b, c).map_or(None, |(t1, t2)| {
             if t1 > tmin && t1 < tmax {
-                let pos = ray.at(t1);
-                Some(Intersection {
-                    t: t1,
-                    wo: -ray.dir.norm(),
-                    pos,
-                    normal: (pos - self.center).norm(),
-                })
+                Some(self.intersection(ray, t1))
             } else if t2 > tmin && t2 < tmax {
-                let pos = ray.at(t2);
-                Some(Intersection {
-                    t: t2,
-                    wo: -ray.dir.norm(),
-                    pos,
-                    normal: (pos - self.center).norm(),
-                })
+                Some(self.intersection(ray, t2))
             } else {
                 None
             }
@@ -56,6 +76,23 @@ impl Shape for Sphere {
     fn aabb(&self) -> &Aabb {
         &self.aabb
     }
+    fn area(&self) -> f64 {
+        4.0 * math::PI * self.radius * self.radius
+    }
+    // ellipsoid has no closed form of area, so it is integrated over grid of spherical coordinates
+    fn transformed_area(&self, transform: &Transform) -> f64 {
+        let n = 64;
+        let mut sum = 0.0;
+        for i in 0..n {
+            let theta = math::PI * (i as f64 + 0.5) / n as f64;
+            for j in 0..(2 * n) {
+                let phi = math::PI * (j as f64 + 0.5) / n as f64;
+                let normal = vec3(theta.sin() * phi.cos(), theta.cos(), theta.sin() * phi.sin());
+                sum += area_scale(transform, normal) * theta.sin();
+            }
+        }
+        sum * self.radius * self.radius * math::PI * math::PI / (n * n) as f64
+    }
 }
 
 
@@ -87,12 +124,9 @@ impl Shape for Rect {
         let hw = self.width * 0.5;
         let hh = self.height * 0.5;
         if pos.x > -hw && pos.x < hw && pos.z > -hh && pos.z < hh {
-            Some(Intersection {
-                t,
-                pos,
-                wo: -ray.dir.norm(),
-                normal: vec3(0.0, 1.0, 0.0),
-            })
+            let uv = (pos.x / self.width + 0.5, pos.z / self.height + 0.5);
+            let (dpdu, dpdv) = (vec3(self.width, 0.0, 0.0), vec3(0.0, 0.0, self.height));
+            Some(Intersection::new(t, -ray.dir.norm(), pos, vec3(0.0, 1.0, 0.0), uv, dpdu, dpdv))
         } else {
             None
         }
@@ -103,29 +137,275 @@ impl Shape for Rect {
     fn sample(&self) -> (Vector3, f64) {
         let hw = 0.5 * self.width;
         let hh = 0.5 * self.height;
-        let pos = vec3(rand::random::<f64>() * self.width - hw, 0.0, rand::random::<f64>() * self.height - hh);
+        let pos = vec3(math::random() * self.width - hw, 0.0, math::random() * self.height - hh);
         let prob = 1.0 / (self.width * self.height);
         (pos, prob)
     }
+    fn area(&self) -> f64 {
+        self.width * self.height
+    }
+    fn transformed_area(&self, transform: &Transform) -> f64 {
+        self.area() * area_scale(transform, vec3(0.0, 1.0, 0.0))
+    }
 }
 
+// texture coordinates at each vertex of triangle
+type TriangleUvs = ((f64, f64), (f64, f64), (f64, f64));
+
 pub struct Triangle {
     positions: (Vector3, Vector3, Vector3),
     normals: (Vector3, Vector3, Vector3),
+    uvs: Option<TriangleUvs>,
+    tangents: Option<(Vector3, Vector3, Vector3)>,
     aabb: Aabb,
 }
 
 impl Triangle {
     pub fn new(positions: (Vector3, Vector3, Vector3), normals: (Vector3, Vector3, Vector3)) -> Triangle {
+        Triangle::create(positions, normals, None, None)
+    }
+    // tangents are derived from texture coordinates
+    pub fn with_uvs(positions: (Vector3, Vector3, Vector3), normals: (Vector3, Vector3, Vector3), uvs: TriangleUvs) -> Triangle {
+        Triangle::create(positions, normals, Some(uvs), None)
+    }
+    pub fn with_tangents(positions: (Vector3, Vector3, Vector3), normals: (Vector3, Vector3, Vector3), tangents: (Vector3, Vector3, Vector3)) -> Triangle {
+        Triangle::create(positions, normals, None, Some(tangents))
+    }
+    fn create(
+        positions: (Vector3, Vector3, Vector3),
+        normals: (Vector3, Vector3, Vector3),
+        uvs: Option<TriangleUvs>,
+        tangents: Option<(Vector3, Vector3, Vector3)>) -> Triangle
+    {
         let aabb = Aabb::new(
             Vector3::min(positions.0, Vector3::min(positions.1, positions.2)),
             Vector3::max(positions.0, Vector3::max(positions.1, positions.2))
         );
-        Triangle { positions, normals, aabb }
+        Triangle { positions, normals, uvs, tangents, aabb }
+    }
+    pub fn positions(&self) -> (Vector3, Vector3, Vector3) {
+        self.positions
+    }
+    pub fn normals(&self) -> (Vector3, Vector3, Vector3) {
+        self.normals
+    }
+    // triangles whose vertex normals are recomputed as area weighted sum of normals of faces
+    // sharing exactly the same position. faces are oriented by the normals given before
+    pub fn smooth_normals(triangles: &[Triangle]) -> Vec<Triangle> {
+        let key = |v: Vector3| (v.x.to_bits(), v.y.to_bits(), v.z.to_bits());
+        let faces = triangles.iter().map(|triangle| {
+            let (a, b, c) = triangle.positions;
+            let (n0, n1, n2) = triangle.normals;
+            let face = (b - a).cross(c - a);
+            if face.dot(n0 + n1 + n2) < 0.0 { -face } else { face }
+        }).collect::<Vec<Vector3>>();
+        let mut sums = HashMap::new();
+        for (triangle, face) in triangles.iter().zip(faces.iter()) {
+            let (a, b, c) = triangle.positions;
+            for p in &[a, b, c] {
+                *sums.entry(key(*p)).or_insert_with(Vector3::zero) += *face;
+            }
+        }
+        triangles.iter().map(|triangle| {
+            let normal = |p: Vector3, n: Vector3| {
+                let sum = sums[&key(p)];
+                if sum.sq_mag() > 0.0 { sum.norm() } else { n }
+            };
+            let (a, b, c) = triangle.positions;
+            let (n0, n1, n2) = triangle.normals;
+            let normals = (normal(a, n0), normal(b, n1), normal(c, n2));
+            Triangle::create(triangle.positions, normals, triangle.uvs, triangle.tangents)
+        }).collect()
+    }
+    // partial derivatives of position with respect to texture coordinates,
+    // or to barycentric coordinates if texture coordinates are not given
+    fn dpduv(&self) -> (Vector3, Vector3) {
+        let e1 = self.positions.1 - self.positions.0;
+        let e2 = self.positions.2 - self.positions.0;
+        if let Some(uvs) = self.uvs {
+            let (du1, dv1) = (uvs.1 .0 - uvs.0 .0, uvs.1 .1 - uvs.0 .1);
+            let (du2, dv2) = (uvs.2 .0 - uvs.0 .0, uvs.2 .1 - uvs.0 .1);
+            let det = du1 * dv2 - dv1 * du2;
+            if det.abs() > 1e-12 {
+                return ((dv2 * e1 - dv1 * e2) / det, (du1 * e2 - du2 * e1) / det);
+            }
+        }
+        (e1, e2)
+    }
+    // direction of tangent at barycentric coordinates before orthogonalization
+    fn tangent_dir(&self, u: f64, v: f64, dpdu: Vector3) -> Vector3 {
+        self.tangents.map_or(dpdu, |tangents| (1.0 - u - v) * tangents.0 + u * tangents.1 + v * tangents.2)
+    }
+    fn uv(&self, u: f64, v: f64) -> (f64, f64) {
+        self.uvs.map_or((u, v), |uvs| (
+            (1.0 - u - v) * uvs.0 .0 + u * uvs.1 .0 + v * uvs.2 .0,
+            (1.0 - u - v) * uvs.0 .1 + u * uvs.1 .1 + v * uvs.2 .1,
+        ))
+    }
+    fn corner(&self, i: usize) -> MeshVertex {
+        let pick = |v: (Vector3, Vector3, Vector3)| [v.0, v.1, v.2][i];
+        let uv = self.uv([0.0, 1.0, 0.0][i], [0.0, 0.0, 1.0][i]);
+        let tangent = self.tangents.map_or(Vector3::zero(), pick);
+        MeshVertex { pos: pick(self.positions), normal: pick(self.normals).norm(), uv, tangent }
+    }
+
+    // subdivides triangle until edges are shorter than edge_length and displaces the vertices
+    // along normals by height texture multiplied by scale. each edge is divided by its own length
+    // and displaced at the same points from both sides, and finer grid inside the triangle is stitched
+    // to them, so adjacent triangles sharing vertices, normals and texture coordinates share every
+    // vertex on the edge without T-junctions. normals are recomputed from the displaced faces by
+    // Triangle::smooth_normals, which should be applied again over the whole mesh to smooth its edges
+    pub fn displace(&self, height: &PTexture<f64>, scale: f64, edge_length: f64) -> Vec<Triangle> {
+        let corners = [self.corner(0), self.corner(1), self.corner(2)];
+        let (dpdu, dpdv) = self.dpduv();
+        let displace = |vertex: MeshVertex| {
+            let isec = Intersection::new(0.0, vertex.normal, vertex.pos, vertex.normal, vertex.uv, dpdu, dpdv);
+            (vertex.pos + scale * height.value(&isec) * vertex.normal, vertex)
+        };
+
+        // points on edge from each corner to the next.
+        // they are computed from the end point of smaller position to be identical on both sides
+        let edges = [(0, 1), (1, 2), (2, 0)].iter().map(|&(a, b)| {
+            let (pa, pb) = (corners[a].pos, corners[b].pos);
+            let reversed = (pb.x, pb.y, pb.z) < (pa.x, pa.y, pa.z);
+            let (s, e) = if reversed { (b, a) } else { (a, b) };
+            let segments = ((pa - pb).mag() / edge_length).ceil().max(1.0) as usize;
+            let mut points = (0..=segments).map(|k| {
+                if k == 0 {
+                    displace(corners[s])
+                } else if k == segments {
+                    displace(corners[e])
+                } else {
+                    displace(corners[s].lerp(&corners[e], k as f64 / segments as f64))
+                }
+            }).collect::<Vec<(Vector3, MeshVertex)>>();
+            if reversed {
+                points.reverse();
+            }
+            points
+        }).collect::<Vec<Vec<(Vector3, MeshVertex)>>>();
+        let n = edges.iter().map(|points| points.len() - 1).max().unwrap();
+        let lattice = |i: usize, j: usize| displace(MeshVertex::interpolate(&corners, i as f64 / n as f64, j as f64 / n as f64));
+
+        let mut triangles = Vec::new();
+        let mut push = |(a, b, c): ((Vector3, MeshVertex), (Vector3, MeshVertex), (Vector3, MeshVertex))| {
+            let face = (b.0 - a.0).cross(c.0 - a.0);
+            if face.sq_mag() == 0.0 {
+                return;
+            }
+            // flat normal facing the same side as the mesh, which orients the face for smoothing
+            let face = face.norm();
+            let face = if face.dot(a.1.normal + b.1.normal + c.1.normal) < 0.0 { -face } else { face };
+            let uvs = self.uvs.map(|_| (a.1.uv, b.1.uv, c.1.uv));
+            let tangents = self.tangents.map(|_| (a.1.tangent, b.1.tangent, c.1.tangent));
+            triangles.push(Triangle::create((a.0, b.0, c.0), (face, face, face), uvs, tangents));
+        };
+
+        if edges.iter().all(|points| points.len() == n + 1) {
+            // uniform grid whose border is the points on edges
+            let rows = (0..=n).map(|j| (0..=(n - j)).map(|i| {
+                if j == 0 {
+                    edges[0][i]
+                } else if i + j == n {
+                    edges[1][j]
+                } else if i == 0 {
+                    edges[2][n - j]
+                } else {
+                    lattice(i, j)
+                }
+            }).collect()).collect::<Vec<Vec<(Vector3, MeshVertex)>>>();
+            grid_triangles(&rows).into_iter().for_each(&mut push);
+            return Triangle::smooth_normals(&triangles);
+        }
+
+        // edges divided differently are stitched to the grid one step inside,
+        // which shrinks to the centroid when the triangle is divided too coarsely
+        let sides = if n >= 3 {
+            let rows = (1..=(n - 2)).map(|j| (1..=(n - 1 - j)).map(|i| lattice(i, j)).collect())
+                .collect::<Vec<Vec<(Vector3, MeshVertex)>>>();
+            grid_triangles(&rows).into_iter().for_each(&mut push);
+            let m = n - 3;
+            [
+                rows[0].clone(),
+                (0..=m).map(|j| rows[j][m - j]).collect(),
+                (0..=m).rev().map(|j| rows[j][0]).collect(),
+            ]
+        } else {
+            let centroid = displace(MeshVertex::interpolate(&corners, 1.0 / 3.0, 1.0 / 3.0));
+            [vec![centroid], vec![centroid], vec![centroid]]
+        };
+        for (edge, side) in edges.iter().zip(sides.iter()) {
+            stitch(edge, side).into_iter().for_each(&mut push);
+        }
+        Triangle::smooth_normals(&triangles)
     }
 }
 
-fn intersect_triangle(ray: &Ray, positions: (Vector3, Vector3, Vector3)) -> Option<f64> {
+// triangles of triangular grid whose j-th row has one vertex less than the previous
+fn grid_triangles<T: Copy>(rows: &[Vec<T>]) -> Vec<(T, T, T)> {
+    let n = rows.len() - 1;
+    let mut triangles = Vec::new();
+    for j in 0..n {
+        for i in 0..(n - j) {
+            triangles.push((rows[j][i], rows[j][i + 1], rows[j + 1][i]));
+            if i + j + 1 < n {
+                triangles.push((rows[j][i + 1], rows[j + 1][i + 1], rows[j + 1][i]));
+            }
+        }
+    }
+    triangles
+}
+
+// strip of triangles between two polylines running in the same direction,
+// which advances on the polyline whose next point is relatively closer
+fn stitch<T: Copy>(outer: &[T], inner: &[T]) -> Vec<(T, T, T)> {
+    let (m, k) = (outer.len() - 1, inner.len() - 1);
+    let (mut a, mut b) = (0, 0);
+    let mut triangles = Vec::new();
+    while a < m || b < k {
+        if b == k || (a < m && (a + 1) * k <= (b + 1) * m) {
+            triangles.push((outer[a], outer[a + 1], inner[b]));
+            a += 1;
+        } else {
+            triangles.push((outer[a], inner[b + 1], inner[b]));
+            b += 1;
+        }
+    }
+    triangles
+}
+
+// attributes at vertex of triangle for tessellation
+#[derive(Clone, Copy)]
+struct MeshVertex {
+    pos: Vector3,
+    normal: Vector3,
+    uv: (f64, f64),
+    tangent: Vector3,
+}
+
+impl MeshVertex {
+    fn lerp(&self, other: &MeshVertex, t: f64) -> MeshVertex {
+        MeshVertex {
+            pos: self.pos + t * (other.pos - self.pos),
+            normal: (self.normal + t * (other.normal - self.normal)).norm(),
+            uv: (self.uv.0 + t * (other.uv.0 - self.uv.0), self.uv.1 + t * (other.uv.1 - self.uv.1)),
+            tangent: self.tangent + t * (other.tangent - self.tangent),
+        }
+    }
+    // interpolates by barycentric coordinates
+    fn interpolate(corners: &[MeshVertex; 3], b1: f64, b2: f64) -> MeshVertex {
+        let b0 = 1.0 - b1 - b2;
+        let [c0, c1, c2] = corners;
+        MeshVertex {
+            pos: b0 * c0.pos + b1 * c1.pos + b2 * c2.pos,
+            normal: (b0 * c0.normal + b1 * c1.normal + b2 * c2.normal).norm(),
+            uv: (b0 * c0.uv.0 + b1 * c1.uv.0 + b2 * c2.uv.0, b0 * c0.uv.1 + b1 * c1.uv.1 + b2 * c2.uv.1),
+            tangent: b0 * c0.tangent + b1 * c1.tangent + b2 * c2.tangent,
+        }
+    }
+}
+
+fn intersect_triangle(ray: &Ray, positions: (Vector3, Vector3, Vector3)) -> Option<(f64, f64, f64)> {
     let e1 = positions.1 - positions.0;
     let e2 = positions.2 - positions.0;
     let alpha = ray.dir.cross(e2);
@@ -153,7 +433,7 @@ fn intersect_triangle(ray: &Ray, positions: (Vector3, Vector3, Vector3)) -> Opti
         return None;
     }
 
-    Some(t)
+    Some((t, u, v))
 }
 
 pub fn interpolate_normal(pos: Vector3, positions: (Vector3, Vector3, Vector3), normals: (Vector3, Vector3, Vector3)) -> Vector3 {
@@ -166,21 +446,190 @@ pub fn interpolate_normal(pos: Vector3, positions: (Vector3, Vector3, Vector3),
 impl Shape for Triangle {
     fn hit(&self, ray: &Ray, tmin: f64, tmax: f64) -> Option<Intersection> {
         intersect_triangle(ray, self.positions)
-            .filter(|t| *t > tmin && *t < tmax)
-            .map(|t| {
+            .filter(|(t, _, _)| *t > tmin && *t < tmax)
+            .map(|(t, u, v)| {
                 let pos = ray.at(t);
                 let normal = interpolate_normal(pos, self.positions, self.normals);
+                // face normal is oriented to the same side as vertex normals
+                let face = (self.positions.1 - self.positions.0).cross(self.positions.2 - self.positions.0).norm();
+                let geometric_normal = if face.dot(normal) < 0.0 { -face } else { face };
+                let (dpdu, dpdv) = self.dpduv();
                 Intersection {
-                    t,
-                    wo: -ray.dir.norm(),
-                    pos,
-                    normal,
+                    geometric_normal,
+                    tangent: math::tangent(normal, self.tangent_dir(u, v, dpdu)),
+                    ..Intersection::new(t, -ray.dir.norm(), pos, normal, self.uv(u, v), dpdu, dpdv)
                 }
             })
     }
     fn aabb(&self) -> &Aabb {
         &self.aabb
     }
+    fn area(&self) -> f64 {
+        0.5 * (self.positions.1 - self.positions.0).cross(self.positions.2 - self.positions.0).mag()
+    }
+    fn transformed_area(&self, transform: &Transform) -> f64 {
+        let (p0, p1, p2) = (transform.point(self.positions.0), transform.point(self.positions.1), transform.point(self.positions.2));
+        0.5 * (p1 - p0).cross(p2 - p0).mag()
+    }
+}
+
+// cubic bezier curve of flat ribbon always facing the ray, used for hair and fur.
+// u is parameter along the curve and v is across the width.
+pub struct Curve {
+    points: [Vector3; 4],
+    widths: (f64, f64),
+    max_depth: u32,
+    aabb: Aabb,
+}
+
+impl Curve {
+    pub fn new(points: [Vector3; 4], width0: f64, width1: f64) -> Curve {
+        let half = 0.5 * width0.max(width1);
+        let min = points.iter().fold(Vector3::infinity(), |res, p| Vector3::min(res, *p));
+        let max = points.iter().fold(Vector3::neg_infinity(), |res, p| Vector3::max(res, *p));
+        let aabb = Aabb::new(min - half, max + half);
+
+        // depth of subdivision for which segments are close enough to lines
+        let mut l0: f64 = 0.0;
+        for i in 0..2 {
+            let d = points[i] - 2.0 * points[i + 1] + points[i + 2];
+            l0 = l0.max(d.x.abs()).max(d.y.abs()).max(d.z.abs());
+        }
+        let eps = 0.05 * width0.max(width1);
+        let r = (std::f64::consts::SQRT_2 * 6.0 * l0 / (8.0 * eps)).log2() / 2.0;
+        let max_depth = if r > 0.0 { (r as u32).min(10) } else { 0 };
+        Curve { points, widths: (width0, width1), max_depth, aabb }
+    }
+    fn width(&self, u: f64) -> f64 {
+        (1.0 - u) * self.widths.0 + u * self.widths.1
+    }
+    // returns t along ray direction of unit length and parameters of closest hit
+    fn intersect(&self, points: &[Vector3; 4], u0: f64, u1: f64, depth: u32, tmin: f64, tmax: f64) -> Option<(f64, f64, f64)> {
+        if depth > 0 {
+            let split = subdivide_bezier(points);
+            let us = [u0, 0.5 * (u0 + u1), u1];
+            let mut tmax = tmax;
+            let mut res = None;
+            for seg in 0..2 {
+                let cps = [split[3 * seg], split[3 * seg + 1], split[3 * seg + 2], split[3 * seg + 3]];
+                let half = 0.5 * self.width(us[seg]).max(self.width(us[seg + 1]));
+                let min = cps.iter().fold(Vector3::infinity(), |res, p| Vector3::min(res, *p));
+                let max = cps.iter().fold(Vector3::neg_infinity(), |res, p| Vector3::max(res, *p));
+                if max.x + half < 0.0 || min.x - half > 0.0 || max.y + half < 0.0 || min.y - half > 0.0
+                    || max.z + half < tmin || min.z - half > tmax {
+                    continue;
+                }
+                if let Some(hit) = self.intersect(&cps, us[seg], us[seg + 1], depth - 1, tmin, tmax) {
+                    tmax = hit.0;
+                    res = Some(hit);
+                }
+            }
+            return res;
+        }
+
+        // ray must be between planes perpendicular to curve at both end points
+        let edge = (points[1].y - points[0].y) * -points[0].y + points[0].x * (points[0].x - points[1].x);
+        if edge < 0.0 {
+            return None;
+        }
+        let edge = (points[2].y - points[3].y) * -points[3].y + points[3].x * (points[3].x - points[2].x);
+        if edge < 0.0 {
+            return None;
+        }
+
+        // parameter of the closest point on line segment to the ray
+        let (dx, dy) = (points[3].x - points[0].x, points[3].y - points[0].y);
+        let denom = dx * dx + dy * dy;
+        if denom == 0.0 {
+            return None;
+        }
+        let w = (-points[0].x * dx - points[0].y * dy) / denom;
+        let u = math::clamp((1.0 - w) * u0 + w * u1, u0, u1);
+        let width = self.width(u);
+        let (pc, dpcdw) = eval_bezier(points, math::clamp(w, 0.0, 1.0));
+        let dist2 = pc.x * pc.x + pc.y * pc.y;
+        if dist2 > 0.25 * width * width || pc.z <= tmin || pc.z >= tmax {
+            return None;
+        }
+        let dist = dist2.sqrt();
+        let edge = dpcdw.x * -pc.y + pc.x * dpcdw.y;
+        let v = if edge > 0.0 { 0.5 - dist / width } else { 0.5 + dist / width };
+        Some((pc.z, u, v))
+    }
+}
+
+fn subdivide_bezier(p: &[Vector3; 4]) -> [Vector3; 7] {
+    [
+        p[0],
+        (p[0] + p[1]) / 2.0,
+        (p[0] + 2.0 * p[1] + p[2]) / 4.0,
+        (p[0] + 3.0 * p[1] + 3.0 * p[2] + p[3]) / 8.0,
+        (p[1] + 2.0 * p[2] + p[3]) / 4.0,
+        (p[2] + p[3]) / 2.0,
+        p[3],
+    ]
+}
+
+// position and derivative of cubic bezier curve
+fn eval_bezier(p: &[Vector3; 4], u: f64) -> (Vector3, Vector3) {
+    let lerp = |a: Vector3, b: Vector3| (1.0 - u) * a + u * b;
+    let (a0, a1, a2) = (lerp(p[0], p[1]), lerp(p[1], p[2]), lerp(p[2], p[3]));
+    let (b0, b1) = (lerp(a0, a1), lerp(a1, a2));
+    let deriv = if (b1 - b0).sq_mag() > 0.0 { 3.0 * (b1 - b0) } else { p[3] - p[0] };
+    (lerp(b0, b1), deriv)
+}
+
+impl Shape for Curve {
+    fn hit(&self, ray: &Ray, tmin: f64, tmax: f64) -> Option<Intersection> {
+        // transform control points to coordinate system where ray starts at origin toward +z
+        let len = ray.dir.mag();
+        let z = ray.dir / len;
+        let x = z.cross(self.points[3] - self.points[0]);
+        let x = if x.sq_mag() > 0.0 { x.norm() } else { math::tangent(z, vec3(1.0, 0.0, 0.0)) };
+        let y = z.cross(x);
+        let to_ray = |p: Vector3| {
+            let d = p - ray.org;
+            vec3(d.dot(x), d.dot(y), d.dot(z))
+        };
+        let points = [to_ray(self.points[0]), to_ray(self.points[1]), to_ray(self.points[2]), to_ray(self.points[3])];
+
+        let (dist, u, v) = self.intersect(&points, 0.0, 1.0, self.max_depth, tmin * len, tmax * len)?;
+        let t = dist / len;
+        let pos = ray.at(t);
+        let (_, dpdu) = eval_bezier(&self.points, u);
+        // ribbon spreads perpendicular to both of curve and ray, and its normal faces the ray.
+        // v decreases toward the left side of the curve seen from the ray
+        let dpdu_ray = to_ray(ray.org + dpdu);
+        let left = (-dpdu_ray.y * x + dpdu_ray.x * y).norm();
+        let dpdv = -self.width(u) * left;
+        let normal = dpdu.cross(dpdv).norm();
+        Some(Intersection::new(t, -z, pos, normal, (u, v), dpdu, dpdv))
+    }
+    fn aabb(&self) -> &Aabb {
+        &self.aabb
+    }
+    fn area(&self) -> f64 {
+        let n = 16;
+        (0..n).map(|i| {
+            let (u0, u1) = (i as f64 / n as f64, (i + 1) as f64 / n as f64);
+            let length = (eval_bezier(&self.points, u1).0 - eval_bezier(&self.points, u0).0).mag();
+            length * self.width(0.5 * (u0 + u1))
+        }).sum()
+    }
+    // width is scaled by square root of area scale of cross section, as ribbon turns toward any ray
+    fn transformed_area(&self, transform: &Transform) -> f64 {
+        let n = 16;
+        (0..n).map(|i| {
+            let (u0, u1) = (i as f64 / n as f64, (i + 1) as f64 / n as f64);
+            let d = eval_bezier(&self.points, u1).0 - eval_bezier(&self.points, u0).0;
+            let length = transform.vector(d).mag();
+            if length == 0.0 {
+                return 0.0;
+            }
+            let cross_section = area_scale(transform, d.norm()) * d.mag() / length;
+            length * cross_section.sqrt() * self.width(0.5 * (u0 + u1))
+        }).sum()
+    }
 }
 
 // pub struct Aggregate {
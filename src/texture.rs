@@ -0,0 +1,275 @@
+use std::ops::{ Add, Mul };
+use std::sync::Arc;
+
+use crate::{ vec3, Vector3 };
+use crate::{ Intersection, SurfaceDifferential, HdrImage };
+use crate::{ color, noise };
+
+pub type PTexture<T> = dyn Texture<T> + Sync + Send + 'static;
+
+pub trait Texture<T> {
+    fn value(&self, isec: &Intersection) -> T;
+}
+
+pub struct ConstantTexture<T> {
+    value: T,
+}
+
+impl<T: Copy> ConstantTexture<T> {
+    pub fn new(value: T) -> ConstantTexture<T> {
+        ConstantTexture { value }
+    }
+}
+
+impl<T: Copy> Texture<T> for ConstantTexture<T> {
+    fn value(&self, _isec: &Intersection) -> T {
+        self.value
+    }
+}
+
+// shared constant texture for parameters given as plain values
+pub fn constant<T: Copy + Sync + Send + 'static>(value: T) -> Arc<PTexture<T>> {
+    Arc::new(ConstantTexture::new(value))
+}
+
+// alternates two textures like checkerboard with frequency of checks per unit
+pub struct CheckerTexture<T> {
+    even: Arc<PTexture<T>>,
+    odd: Arc<PTexture<T>>,
+    frequency: f64,
+    solid: bool,
+}
+
+impl<T> CheckerTexture<T> {
+    // checks on texture coordinates
+    pub fn new(even: Arc<PTexture<T>>, odd: Arc<PTexture<T>>, frequency: f64) -> CheckerTexture<T> {
+        CheckerTexture { even, odd, frequency, solid: false }
+    }
+    // cubic checks on position of intersection
+    pub fn solid(even: Arc<PTexture<T>>, odd: Arc<PTexture<T>>, frequency: f64) -> CheckerTexture<T> {
+        CheckerTexture { even, odd, frequency, solid: true }
+    }
+}
+
+impl<T> Texture<T> for CheckerTexture<T> {
+    fn value(&self, isec: &Intersection) -> T {
+        let f = self.frequency;
+        let sum = if self.solid {
+            (isec.pos.x * f).floor() + (isec.pos.y * f).floor() + (isec.pos.z * f).floor()
+        } else {
+            (isec.uv.0 * f).floor() + (isec.uv.1 * f).floor()
+        };
+        if sum.rem_euclid(2.0) == 0.0 {
+            self.even.value(isec)
+        } else {
+            self.odd.value(isec)
+        }
+    }
+}
+
+// pattern of procedural noise mapped into [0, 1]
+#[derive(Debug, Clone, Copy)]
+pub enum Noise {
+    Perlin,
+    // number of octaves
+    Fbm(u32),
+    Turbulence(u32),
+    // distance to the closest feature point
+    Worley,
+    // veins along y axis distorted by turbulence of the strength
+    Marble(f64),
+    // rings around y axis distorted by fbm of the strength
+    Wood(f64),
+}
+
+impl Noise {
+    fn value(&self, p: Vector3) -> f64 {
+        let t = match *self {
+            Noise::Perlin => 0.5 + 0.5 * noise::perlin(p),
+            Noise::Fbm(octaves) => 0.5 + 0.5 * noise::fbm(p, octaves),
+            Noise::Turbulence(octaves) => noise::turbulence(p, octaves),
+            Noise::Worley => noise::worley(p).0,
+            Noise::Marble(strength) => {
+                0.5 + 0.5 * (p.y + strength * noise::turbulence(p, 6)).sin()
+            },
+            Noise::Wood(strength) => {
+                let r = (p.x * p.x + p.z * p.z).sqrt() + strength * noise::fbm(p, 4);
+                // sharp edge at the end of each ring
+                (r - r.floor()).powi(3)
+            },
+        };
+        t.clamp(0.0, 1.0)
+    }
+}
+
+// blends two textures by solid noise, which does not require texture coordinates
+pub struct NoiseTexture<T> {
+    low: Arc<PTexture<T>>,
+    high: Arc<PTexture<T>>,
+    noise: Noise,
+    frequency: f64,
+    object: bool,
+}
+
+impl<T> NoiseTexture<T> {
+    // noise on position in world space
+    pub fn new(low: Arc<PTexture<T>>, high: Arc<PTexture<T>>, noise: Noise, frequency: f64) -> NoiseTexture<T> {
+        NoiseTexture { low, high, noise, frequency, object: false }
+    }
+    // noise on position in space of shape, which moves along with the shape
+    pub fn object(low: Arc<PTexture<T>>, high: Arc<PTexture<T>>, noise: Noise, frequency: f64) -> NoiseTexture<T> {
+        NoiseTexture { low, high, noise, frequency, object: true }
+    }
+}
+
+impl<T: Add<Output = T> + Mul<f64, Output = T>> Texture<T> for NoiseTexture<T> {
+    fn value(&self, isec: &Intersection) -> T {
+        let pos = if self.object { isec.object_pos } else { isec.pos };
+        let t = self.noise.value(self.frequency * pos);
+        self.low.value(isec) * (1.0 - t) + self.high.value(isec) * t
+    }
+}
+
+// blends three projections of texture along axes by normal in space of shape,
+// which does not require texture coordinates. frequency is repeat of texture per unit
+// and larger sharpness narrows transitions between projections
+pub struct TriplanarTexture<T> {
+    texture: Arc<PTexture<T>>,
+    frequency: f64,
+    sharpness: f64,
+}
+
+impl<T> TriplanarTexture<T> {
+    pub fn new(texture: Arc<PTexture<T>>, frequency: f64, sharpness: f64) -> TriplanarTexture<T> {
+        TriplanarTexture { texture, frequency, sharpness }
+    }
+}
+
+impl<T: Add<Output = T> + Mul<f64, Output = T>> Texture<T> for TriplanarTexture<T> {
+    fn value(&self, isec: &Intersection) -> T {
+        let n = isec.object_normal;
+        let weights = vec3(n.x.abs().powf(self.sharpness), n.y.abs().powf(self.sharpness), n.z.abs().powf(self.sharpness));
+        let weights = weights / (weights.x + weights.y + weights.z);
+        // projections of negligible weight are skipped
+        let weights = vec3(
+            if weights.x > 1e-4 { weights.x } else { 0.0 },
+            if weights.y > 1e-4 { weights.y } else { 0.0 },
+            if weights.z > 1e-4 { weights.z } else { 0.0 },
+        );
+        let weights = weights / (weights.x + weights.y + weights.z);
+        let (p, f) = (isec.object_pos, self.frequency);
+        // u is flipped on back side so that projection is not mirrored
+        let sign = |v: f64| if v < 0.0 { -1.0 } else { 1.0 };
+        let project = |axis: usize, v: Vector3| match axis {
+            0 => (-sign(n.x) * v.z, v.y),
+            1 => (v.x, -sign(n.y) * v.z),
+            _ => (sign(n.z) * v.x, v.y),
+        };
+        (0..3)
+            .filter(|axis| weights[*axis] > 0.0)
+            .map(|axis| {
+                let uv = project(axis, f * p);
+                // footprint is carried into space of shape along derivatives of the surface
+                let object = |duv: (f64, f64)| duv.0 * isec.object_dpdu + duv.1 * isec.object_dpdv;
+                let differential = isec.differential.map(|d| {
+                    SurfaceDifferential { duvdx: project(axis, f * object(d.duvdx)), duvdy: project(axis, f * object(d.duvdy)), ..d }
+                });
+                self.texture.value(&Intersection { uv, differential, ..*isec }) * weights[axis]
+            })
+            .reduce(|a, b| a + b)
+            .unwrap()
+    }
+}
+
+// level of mipmap whose texels are decoded into linear values
+struct MipLevel {
+    width: usize,
+    height: usize,
+    texels: Vec<Vector3>,
+}
+
+impl MipLevel {
+    // texture repeats outside of [0, 1]
+    fn texel(&self, x: i64, y: i64) -> Vector3 {
+        let x = x.rem_euclid(self.width as i64) as usize;
+        let y = y.rem_euclid(self.height as i64) as usize;
+        self.texels[y * self.width + x]
+    }
+    fn bilinear(&self, u: f64, v: f64) -> Vector3 {
+        let x = u * self.width as f64 - 0.5;
+        let y = (1.0 - v) * self.height as f64 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        (1.0 - ty) * ((1.0 - tx) * self.texel(x0, y0) + tx * self.texel(x0 + 1, y0))
+            + ty * ((1.0 - tx) * self.texel(x0, y0 + 1) + tx * self.texel(x0 + 1, y0 + 1))
+    }
+    // box filter of 2x2 texels, where the last texel of odd size is repeated
+    fn downsample(&self) -> MipLevel {
+        let (width, height) = ((self.width / 2).max(1), (self.height / 2).max(1));
+        let at = |x: usize, y: usize| self.texels[y.min(self.height - 1) * self.width + x.min(self.width - 1)];
+        let texels = (0..height).flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| 0.25 * (at(2 * x, 2 * y) + at(2 * x + 1, 2 * y) + at(2 * x, 2 * y + 1) + at(2 * x + 1, 2 * y + 1)))
+            .collect();
+        MipLevel { width, height, texels }
+    }
+}
+
+// image filtered trilinearly over mipmap by footprint of ray differential,
+// or bilinearly on the finest level without differential
+pub struct ImageTexture {
+    levels: Vec<MipLevel>,
+}
+
+impl ImageTexture {
+    // color image encoded in sRGB. floating point images of .hdr, .pfm and .exr are always linear
+    pub fn new(filename: &str) -> ImageTexture {
+        ImageTexture::load(filename, color::srgb_to_linear)
+    }
+    // image of non-color data like roughness or mask whose values are stored linearly
+    pub fn linear(filename: &str) -> ImageTexture {
+        ImageTexture::load(filename, |v| v)
+    }
+    fn load(filename: &str, decode: fn(f64) -> f64) -> ImageTexture {
+        let image = HdrImage::open(filename, decode);
+        let level = MipLevel { width: image.width(), height: image.height(), texels: image.pixels().to_vec() };
+        let mut levels = vec![level];
+        while levels[levels.len() - 1].width > 1 || levels[levels.len() - 1].height > 1 {
+            let next = levels[levels.len() - 1].downsample();
+            levels.push(next);
+        }
+        ImageTexture { levels }
+    }
+    // level where footprint of pixel covers about one texel
+    fn level(&self, isec: &Intersection) -> f64 {
+        let (width, height) = (self.levels[0].width as f64, self.levels[0].height as f64);
+        let footprint = isec.differential.map_or(0.0, |d| {
+            let x = (d.duvdx.0 * width).hypot(d.duvdx.1 * height);
+            let y = (d.duvdy.0 * width).hypot(d.duvdy.1 * height);
+            x.max(y)
+        });
+        footprint.max(1.0).log2().min((self.levels.len() - 1) as f64)
+    }
+}
+
+impl Texture<Vector3> for ImageTexture {
+    fn value(&self, isec: &Intersection) -> Vector3 {
+        let (u, v) = isec.uv;
+        let level = self.level(isec);
+        let l0 = level.floor() as usize;
+        let t = level - level.floor();
+        let value = self.levels[l0].bilinear(u, v);
+        if t > 0.0 {
+            (1.0 - t) * value + t * self.levels[l0 + 1].bilinear(u, v)
+        } else {
+            value
+        }
+    }
+}
+
+// luminance of image is used as scalar value like mask or weight
+impl Texture<f64> for ImageTexture {
+    fn value(&self, isec: &Intersection) -> f64 {
+        color::luminance(Texture::<Vector3>::value(self, isec))
+    }
+}
@@ -1,5 +1,5 @@
 use crate::{ vec3, Vector3, Matrix4 };
-use crate::{ Ray, Aabb };
+use crate::{ Ray, RayDifferential, Aabb };
 
 #[derive(Debug)]
 pub struct Transform {
@@ -129,7 +129,15 @@ impl Transform {
     }
 
     pub fn ray(&self, ray: &Ray) -> Ray {
-        Ray::new(self.point(ray.org), self.vector(ray.dir))
+        match &ray.differential {
+            None => Ray::new(self.point(ray.org), self.vector(ray.dir)),
+            Some(differential) => Ray::with_differential(self.point(ray.org), self.vector(ray.dir), RayDifferential {
+                rx_org: self.point(differential.rx_org),
+                rx_dir: self.vector(differential.rx_dir),
+                ry_org: self.point(differential.ry_org),
+                ry_dir: self.vector(differential.ry_dir),
+            }),
+        }
     }
 
     pub fn aabb(&self, aabb: &Aabb) -> Aabb {
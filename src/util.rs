@@ -1,13 +1,15 @@
 use std::path::Path;
 
 use crate::vec3;
-use crate::Triangle;
+use crate::{ Triangle, PTexture };
+use crate::IesProfile;
 
 pub fn load_obj(filename: &str) -> Vec<Box<Triangle>> {
     let (models, _) = tobj::load_obj(&Path::new(filename)).unwrap();
     let mesh = &models[0].mesh;
     let positions = &mesh.positions;
     let normals = &mesh.normals;
+    let texcoords = &mesh.texcoords;
     let indices = &mesh.indices;
     indices.chunks(3).map(|idx| {
         let v0 = idx[0] as usize * 3;
@@ -23,7 +25,16 @@ pub fn load_obj(filename: &str) -> Vec<Box<Triangle>> {
             vec3(normals[v1] as f64, normals[v1 + 1] as f64, normals[v1 + 2] as f64),
             vec3(normals[v2] as f64, normals[v2 + 1] as f64, normals[v2 + 2] as f64),
         );
-        Box::new(Triangle::new(positions, normals))
+        if texcoords.is_empty() {
+            return Box::new(Triangle::new(positions, normals));
+        }
+        let (t0, t1, t2) = (idx[0] as usize * 2, idx[1] as usize * 2, idx[2] as usize * 2);
+        let uvs = (
+            (texcoords[t0] as f64, texcoords[t0 + 1] as f64),
+            (texcoords[t1] as f64, texcoords[t1 + 1] as f64),
+            (texcoords[t2] as f64, texcoords[t2 + 1] as f64),
+        );
+        Box::new(Triangle::with_uvs(positions, normals, uvs))
     }).collect()
 }
 
@@ -45,4 +56,20 @@ pub fn load_stl(filename: &str) -> Vec<Box<Triangle>> {
         let normals = (normal, normal, normal);
         Box::new(Triangle::new(positions, normals))
     }).collect()
+}
+
+// tessellates and displaces mesh by height texture, see Triangle::displace.
+// normals are smoothed over the whole mesh so that shading is continuous across the original edges
+pub fn displace_mesh(triangles: &[Box<Triangle>], height: &PTexture<f64>, scale: f64, edge_length: f64) -> Vec<Box<Triangle>> {
+    let displaced = triangles.iter()
+        .flat_map(|triangle| triangle.displace(height, scale, edge_length))
+        .collect::<Vec<Triangle>>();
+    Triangle::smooth_normals(&displaced).into_iter()
+        .map(Box::new)
+        .collect()
+}
+
+pub fn load_ies(filename: &str) -> IesProfile {
+    let text = std::fs::read_to_string(filename).unwrap();
+    IesProfile::parse(&text).expect("unsupported IES file")
 }
\ No newline at end of file
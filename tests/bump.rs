@@ -0,0 +1,47 @@
+extern crate raytracing_study;
+
+use std::sync::Arc;
+
+use raytracing_study::{ vec3, Vector3, Transform, Intersection, Texture };
+use raytracing_study::{ Ray, Scene, PPrimitive, Geometry, TransformedPrimitive, Sphere };
+use raytracing_study::{ PMaterial, LambertMaterial, NormalMapMaterial };
+
+// height varying over space of shape
+struct Waves;
+
+impl Texture<f64> for Waves {
+    fn value(&self, isec: &Intersection) -> f64 {
+        (7.0 * isec.object_pos.x).sin() * (5.0 * isec.object_pos.y).cos() + (3.0 * isec.object_pos.z).sin()
+    }
+}
+
+fn sphere() -> Box<PPrimitive> {
+    let base: Arc<PMaterial> = Arc::new(LambertMaterial::new(vec3(0.5, 0.5, 0.5)));
+    let material: Arc<PMaterial> = Arc::new(NormalMapMaterial::bump(base, Arc::new(Waves), 0.05));
+    Box::new(Geometry::new(Box::new(Sphere::new(Vector3::zero(), 1.0)), material))
+}
+
+fn rotation() -> Transform {
+    Transform::rotate_y(35.0).transform(&Transform::rotate_x(50.0))
+}
+
+// closest hit with shading frame of the material
+fn hit(primitive: Box<PPrimitive>, ray: &Ray) -> Intersection {
+    Scene::new(vec![primitive]).hit(ray).expect("ray misses the sphere").0
+}
+
+#[test]
+fn bump_follows_rotated_primitive() {
+    let rotation = rotation();
+    for dir in &[vec3(0.05, 0.1, 1.0), vec3(-0.12, 0.04, 1.0), vec3(0.08, -0.15, 1.0)] {
+        let ray = Ray::new(vec3(0.0, 0.0, -5.0), dir.norm());
+        let isec = hit(sphere(), &ray);
+        // bump actually perturbs the normal
+        assert!((isec.normal - isec.geometric_normal).mag() > 1e-3);
+        // the same point of the shape is hit by the rotated ray, so the perturbed normal is rotated as well
+        let rotated_ray = Ray::new(rotation.point(ray.org), rotation.vector(ray.dir));
+        let rotated = hit(Box::new(TransformedPrimitive::new(sphere(), self::rotation())), &rotated_ray).normal;
+        let expected = rotation.normal(isec.normal).norm();
+        assert!((rotated - expected).mag() < 1e-6, "normal {:?} but expected {:?}", rotated, expected);
+    }
+}
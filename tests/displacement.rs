@@ -0,0 +1,122 @@
+extern crate raytracing_study;
+
+use std::collections::HashMap;
+
+use raytracing_study::{ vec3, Vector3, Intersection, Texture, Triangle, util };
+
+struct Waves;
+
+impl Texture<f64> for Waves {
+    fn value(&self, isec: &Intersection) -> f64 {
+        (5.0 * isec.pos.x).sin() * (3.0 * isec.pos.y).cos() + (4.0 * isec.pos.z).sin()
+    }
+}
+
+// positions are compared exactly
+type Key = (u64, u64, u64);
+
+fn key(v: Vector3) -> Key {
+    (v.x.to_bits(), v.y.to_bits(), v.z.to_bits())
+}
+
+fn flat(positions: (Vector3, Vector3, Vector3)) -> Triangle {
+    let normal = (positions.1 - positions.0).cross(positions.2 - positions.0).norm();
+    Triangle::new(positions, (normal, normal, normal))
+}
+
+// number of triangles using each undirected edge
+fn edge_counts(triangles: &[Triangle]) -> HashMap<(Key, Key), usize> {
+    let mut counts = HashMap::new();
+    for triangle in triangles {
+        let (a, b, c) = triangle.positions();
+        for (p, q) in &[(a, b), (b, c), (c, a)] {
+            let (p, q) = (key(*p), key(*q));
+            let edge = if p < q { (p, q) } else { (q, p) };
+            *counts.entry(edge).or_insert(0) += 1;
+        }
+    }
+    counts
+}
+
+fn segments(a: Vector3, b: Vector3, edge_length: f64) -> usize {
+    ((a - b).mag() / edge_length).ceil() as usize
+}
+
+#[test]
+fn shared_edge_of_different_subdivision() {
+    let edge_length = 0.1;
+    let (a, b, c, d) = (vec3(0.0, 0.0, 0.0), vec3(1.0, 0.0, 0.0), vec3(0.0, 1.0, 0.0), vec3(2.5, 2.0, 0.0));
+    // the small triangle is divided less finely than the large one
+    let small = flat((a, b, c)).displace(&Waves, 0.05, edge_length);
+    let large = flat((b, d, c)).displace(&Waves, 0.05, edge_length);
+    let small_edges = edge_counts(&small);
+    let large_edges = edge_counts(&large);
+    // segments of the shared edge are on the border of both
+    let shared = small_edges.iter().filter(|(edge, count)| **count == 1 && large_edges.get(edge) == Some(&1)).count();
+    assert_eq!(shared, segments(b, c, edge_length));
+
+    let mut triangles = small;
+    triangles.extend(large);
+    let counts = edge_counts(&triangles);
+    assert!(counts.values().all(|count| *count <= 2));
+    let border = counts.values().filter(|count| **count == 1).count();
+    let outer = segments(a, b, edge_length) + segments(c, a, edge_length) + segments(b, d, edge_length) + segments(d, c, edge_length);
+    assert_eq!(border, outer);
+}
+
+#[test]
+fn closed_mesh_stays_closed() {
+    // tetrahedron with edges of various lengths, including coarse ones which shrink inner grid to the centroid
+    let p = [vec3(0.0, 0.0, 0.0), vec3(1.3, 0.0, 0.1), vec3(0.2, 0.7, 0.0), vec3(0.4, 0.3, 2.1)];
+    let faces = [(0, 2, 1), (0, 1, 3), (1, 2, 3), (2, 0, 3)];
+    // vertices share normals pointing away from the center
+    let center = 0.25 * (p[0] + p[1] + p[2] + p[3]);
+    let normal = |i: usize| (p[i] - center).norm();
+    for edge_length in &[0.15, 0.6, 1.0, 5.0] {
+        let triangles = faces.iter()
+            .map(|&(i, j, k)| Triangle::new((p[i], p[j], p[k]), (normal(i), normal(j), normal(k))))
+            .flat_map(|triangle| triangle.displace(&Waves, 0.1, *edge_length))
+            .collect::<Vec<Triangle>>();
+        assert!(edge_counts(&triangles).values().all(|count| *count == 2), "open mesh for edge length {}", edge_length);
+    }
+}
+
+#[test]
+fn normals_are_smooth_over_mesh() {
+    let p = [vec3(0.0, 0.0, 0.0), vec3(1.3, 0.0, 0.1), vec3(0.2, 0.7, 0.0), vec3(0.4, 0.3, 2.1)];
+    let faces = [(0, 2, 1), (0, 1, 3), (1, 2, 3), (2, 0, 3)];
+    let center = 0.25 * (p[0] + p[1] + p[2] + p[3]);
+    let normal = |i: usize| (p[i] - center).norm();
+    let mesh = faces.iter()
+        .map(|&(i, j, k)| Box::new(Triangle::new((p[i], p[j], p[k]), (normal(i), normal(j), normal(k)))))
+        .collect::<Vec<Box<Triangle>>>();
+    let displaced = util::displace_mesh(&mesh, &Waves, 0.1, 0.15);
+    // every vertex has one unit normal, also on edges of the original mesh
+    let mut normals: HashMap<Key, Vector3> = HashMap::new();
+    for triangle in &displaced {
+        let (a, b, c) = triangle.positions();
+        let (na, nb, nc) = triangle.normals();
+        for (p, n) in &[(a, na), (b, nb), (c, nc)] {
+            assert!((n.mag() - 1.0).abs() < 1e-9);
+            let shared = *normals.entry(key(*p)).or_insert(*n);
+            assert_eq!(key(shared), key(*n), "normals differ at {:?}", p);
+        }
+    }
+    // normals are not flat across faces
+    assert!(displaced.iter().any(|triangle| {
+        let (na, nb, _) = triangle.normals();
+        (na - nb).mag() > 1e-3
+    }));
+}
+
+#[test]
+fn flat_surface_keeps_its_normal() {
+    let normal = vec3(0.0, 0.0, 1.0);
+    let triangle = Triangle::new((vec3(0.0, 0.0, 0.0), vec3(1.0, 0.0, 0.0), vec3(0.0, 1.0, 0.0)), (normal, normal, normal));
+    for triangle in triangle.displace(&Waves, 0.0, 0.1) {
+        let (na, nb, nc) = triangle.normals();
+        for n in &[na, nb, nc] {
+            assert!((*n - normal).mag() < 1e-9, "normal {:?}", n);
+        }
+    }
+}
@@ -0,0 +1,131 @@
+extern crate raytracing_study;
+
+use raytracing_study::{ vec3, Vector3, HdrImage };
+
+// image with smooth gradients and constant halves, so that both runs and literals appear in scanlines
+fn gradient(width: usize, height: usize) -> HdrImage {
+    let mut image = HdrImage::new(width, height);
+    for y in 0..height {
+        for x in 0..width {
+            let blue = if x < width / 2 { 1.0 } else { 3.0 };
+            image.set(x, y, vec3(0.5 * x as f64, 0.25 * y as f64 + 0.125, blue));
+        }
+    }
+    image
+}
+
+// values without pattern
+fn noise(width: usize, height: usize) -> HdrImage {
+    let mut image = HdrImage::new(width, height);
+    let mut h = 12345u32;
+    let mut next = || {
+        h ^= h << 13;
+        h ^= h >> 17;
+        h ^= h << 5;
+        (h % 100000) as f64 / 1024.0
+    };
+    for y in 0..height {
+        for x in 0..width {
+            image.set(x, y, vec3(next(), next(), next()));
+        }
+    }
+    image
+}
+
+fn max_component(v: Vector3) -> f64 {
+    v.x.max(v.y.max(v.z))
+}
+
+// rgbe shares exponent among components, so the error is relative to the largest component
+fn assert_close(a: &HdrImage, b: &HdrImage, tolerance: f64) {
+    assert_eq!((a.width(), a.height()), (b.width(), b.height()));
+    for y in 0..a.height() {
+        for x in 0..a.width() {
+            let (p, q) = (a.get(x, y), b.get(x, y));
+            let error = max_component(vec3((p.x - q.x).abs(), (p.y - q.y).abs(), (p.z - q.z).abs()));
+            assert!(error <= tolerance * max_component(p), "pixel ({}, {}) is {:?} but expected {:?}", x, y, q, p);
+        }
+    }
+}
+
+#[test]
+fn rgbe_round_trip() {
+    let image = gradient(40, 3);
+    let bytes = image.encode_rgbe();
+    // first scanline starts with marker of run length encoding and the width
+    let header = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 3 +X 40\n";
+    assert_eq!(&bytes[..header.len()], &header[..]);
+    assert_eq!(&bytes[header.len()..header.len() + 4], &[2, 2, 0, 40]);
+    assert!(bytes.len() < header.len() + 4 * 40 * 3);
+    assert_close(&image, &HdrImage::decode_rgbe(&bytes).unwrap(), 1.0 / 128.0);
+    // runs longer than 127 are split
+    let wide = gradient(300, 2);
+    assert_close(&wide, &HdrImage::decode_rgbe(&wide.encode_rgbe()).unwrap(), 1.0 / 128.0);
+}
+
+#[test]
+fn rgbe_round_trip_without_run_length_encoding() {
+    // scanlines narrower than 8 pixels are stored flat
+    let image = gradient(5, 4);
+    let bytes = image.encode_rgbe();
+    assert_eq!(bytes.len(), b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 4 +X 5\n".len() + 4 * 5 * 4);
+    assert_close(&image, &HdrImage::decode_rgbe(&bytes).unwrap(), 1.0 / 128.0);
+}
+
+#[test]
+fn rgbe_run_length_scanline() {
+    let mut bytes = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 1 +X 8\n".to_vec();
+    bytes.extend_from_slice(&[2, 2, 0, 8]);
+    // red is a run of 8, green is 8 literals, blue is 3 literals followed by a run of 5
+    bytes.extend_from_slice(&[128 + 8, 128]);
+    bytes.extend_from_slice(&[8, 0, 16, 32, 48, 64, 80, 96, 112]);
+    bytes.extend_from_slice(&[3, 255, 64, 32, 128 + 5, 0]);
+    // exponent of 2^1 for all pixels
+    bytes.extend_from_slice(&[128 + 8, 129]);
+    let image = HdrImage::decode_rgbe(&bytes).unwrap();
+    let blue = [255.0, 64.0, 32.0, 0.0, 0.0, 0.0, 0.0, 0.0];
+    for (x, b) in blue.iter().enumerate() {
+        let p = image.get(x, 0);
+        assert_eq!((p.x, p.y, p.z), (1.0, 16.0 * x as f64 / 128.0, b / 128.0));
+    }
+    // run over the end of scanline
+    let end = bytes.len() - 2;
+    bytes[end] = 128 + 9;
+    assert!(HdrImage::decode_rgbe(&bytes).is_none());
+}
+
+#[test]
+fn pfm_round_trip() {
+    // values are exact in single precision
+    for image in &[gradient(6, 5), noise(3, 7)] {
+        assert_close(image, &HdrImage::decode_pfm(&image.encode_pfm()).unwrap(), 0.0);
+    }
+}
+
+#[test]
+fn exr_round_trip() {
+    for image in &[gradient(12, 16), noise(9, 32)] {
+        assert_close(image, &HdrImage::decode_exr(&image.encode_exr()).unwrap(), 0.0);
+    }
+}
+
+#[test]
+fn exr_height_not_multiple_of_chunk() {
+    // last chunk of 16 scanlines is partial
+    for image in &[gradient(7, 37), noise(5, 21), gradient(3, 1)] {
+        assert_close(image, &HdrImage::decode_exr(&image.encode_exr()).unwrap(), 0.0);
+    }
+}
+
+#[test]
+fn empty_images() {
+    for (width, height) in &[(0, 0), (0, 3), (4, 0)] {
+        let image = HdrImage::new(*width, *height);
+        let decoded = HdrImage::decode_exr(&image.encode_exr()).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (*width, *height));
+        let decoded = HdrImage::decode_pfm(&image.encode_pfm()).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (*width, *height));
+        let decoded = HdrImage::decode_rgbe(&image.encode_rgbe()).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (*width, *height));
+    }
+}
@@ -0,0 +1,103 @@
+extern crate raytracing_study;
+
+use raytracing_study::{ vec3, Vector3, IesProfile };
+
+// minimal type C file with vertical angles 0 and 90, and candela of each horizontal angle
+fn profile(horizontal: &[f64], candela: &[(f64, f64)]) -> String {
+    let count = horizontal.len();
+    let horizontal = horizontal.iter().map(|a| a.to_string()).collect::<Vec<String>>().join(" ");
+    let candela = candela.iter().map(|(c0, c90)| format!("{} {}", c0, c90)).collect::<Vec<String>>().join("\n");
+    format!("IESNA:LM-63-2002\n[TEST] minimal\nTILT=NONE\n1 1000 1 2 {} 1 2 0 0 0\n1 1 100\n0 90\n{}\n{}\n",
+        count, horizontal, candela)
+}
+
+// direction in local space of luminaire at vertical angle 90 and the horizontal angle
+fn horizontal_dir(degrees: f64) -> Vector3 {
+    let phi = degrees.to_radians();
+    vec3(phi.cos(), 0.0, phi.sin())
+}
+
+fn close(a: f64, b: f64) -> bool {
+    (a - b).abs() < 1e-9
+}
+
+#[test]
+fn rotationally_symmetric() {
+    let ies = IesProfile::parse(&profile(&[0.0], &[(100.0, 50.0)])).unwrap();
+    assert!(close(ies.max_candela(), 100.0));
+    assert!(close(ies.candela(vec3(0.0, -1.0, 0.0)), 100.0));
+    assert!(close(ies.candela(horizontal_dir(0.0)), 50.0));
+    assert!(close(ies.candela(horizontal_dir(123.0)), 50.0));
+    // vertical angle above measured range
+    assert!(close(ies.candela(vec3(0.0, 1.0, 0.0)), 0.0));
+}
+
+#[test]
+fn tilt_and_values_over_lines() {
+    // tilt table, keyword line and candela values are split over several lines with commas
+    let text = "IESNA:LM-63-2002\n[MANUFAC] test\nTILT=INCLUDE\n1\n3\n0 45\n90\n1.0, 0.9,\n0.8\n\
+        1 1000 2\n2 1\n1 2 0.1 0.2 0\n1.0 1.0 100\n0\n90\n0\n5,\n10\n";
+    let ies = IesProfile::parse(text).unwrap();
+    // values are scaled by multiplier
+    assert!(close(ies.candela(vec3(0.0, -1.0, 0.0)), 10.0));
+    assert!(close(ies.candela(horizontal_dir(0.0)), 20.0));
+    assert!(close(ies.max_candela(), 20.0));
+}
+
+#[test]
+fn missing_values() {
+    let text = profile(&[0.0], &[(100.0, 50.0)]);
+    assert!(IesProfile::parse(&text[..text.len() - 4]).is_none());
+    assert!(IesProfile::parse(&text.replace("TILT=NONE", "TILT=lamp.tlt")).is_none());
+}
+
+#[test]
+fn quadrant_symmetric() {
+    let ies = IesProfile::parse(&profile(&[0.0, 90.0], &[(0.0, 10.0), (0.0, 30.0)])).unwrap();
+    assert!(close(ies.candela(horizontal_dir(0.0)), 10.0));
+    assert!(close(ies.candela(horizontal_dir(45.0)), 20.0));
+    assert!(close(ies.candela(horizontal_dir(90.0)), 30.0));
+    assert!(close(ies.candela(horizontal_dir(135.0)), 20.0));
+    assert!(close(ies.candela(horizontal_dir(180.0)), 10.0));
+    assert!(close(ies.candela(horizontal_dir(270.0)), 30.0));
+    assert!(close(ies.candela(horizontal_dir(-45.0)), 20.0));
+}
+
+#[test]
+fn bilaterally_symmetric() {
+    let ies = IesProfile::parse(&profile(&[0.0, 90.0, 180.0], &[(0.0, 10.0), (0.0, 30.0), (0.0, 50.0)])).unwrap();
+    assert!(close(ies.candela(horizontal_dir(0.0)), 10.0));
+    assert!(close(ies.candela(horizontal_dir(135.0)), 40.0));
+    assert!(close(ies.candela(horizontal_dir(180.0)), 50.0));
+    assert!(close(ies.candela(horizontal_dir(225.0)), 40.0));
+    assert!(close(ies.candela(horizontal_dir(270.0)), 30.0));
+}
+
+#[test]
+fn asymmetric() {
+    let candela = [(0.0, 10.0), (0.0, 20.0), (0.0, 30.0), (0.0, 40.0), (0.0, 10.0)];
+    let ies = IesProfile::parse(&profile(&[0.0, 90.0, 180.0, 270.0, 360.0], &candela)).unwrap();
+    assert!(close(ies.candela(horizontal_dir(90.0)), 20.0));
+    assert!(close(ies.candela(horizontal_dir(225.0)), 35.0));
+    assert!(close(ies.candela(horizontal_dir(270.0)), 40.0));
+    assert!(close(ies.candela(horizontal_dir(315.0)), 25.0));
+}
+
+#[test]
+fn symmetric_about_90_270_plane() {
+    let ies = IesProfile::parse(&profile(&[90.0, 180.0, 270.0], &[(0.0, 10.0), (0.0, 30.0), (0.0, 50.0)])).unwrap();
+    assert!(close(ies.candela(horizontal_dir(90.0)), 10.0));
+    assert!(close(ies.candela(horizontal_dir(180.0)), 30.0));
+    assert!(close(ies.candela(horizontal_dir(270.0)), 50.0));
+    // mirrored from the other side of the plane
+    assert!(close(ies.candela(horizontal_dir(0.0)), 30.0));
+    assert!(close(ies.candela(horizontal_dir(45.0)), 20.0));
+    assert!(close(ies.candela(horizontal_dir(315.0)), 40.0));
+}
+
+#[test]
+fn unsupported_horizontal_ranges() {
+    assert!(IesProfile::parse(&profile(&[45.0, 135.0], &[(1.0, 1.0), (1.0, 1.0)])).is_none());
+    assert!(IesProfile::parse(&profile(&[0.0, 270.0], &[(1.0, 1.0), (1.0, 1.0)])).is_none());
+    assert!(IesProfile::parse(&profile(&[-90.0, 90.0], &[(1.0, 1.0), (1.0, 1.0)])).is_none());
+}
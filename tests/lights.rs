@@ -0,0 +1,40 @@
+extern crate raytracing_study;
+
+use raytracing_study::{ vec3, math, Transform, Ray, Primitive, Geometry, TransformedPrimitive };
+use raytracing_study::{ Shape, Sphere, Rect, Triangle, LightUnit };
+
+fn close(a: f64, b: f64, tolerance: f64) -> bool {
+    (a - b).abs() <= tolerance * a.abs().max(b.abs())
+}
+
+// radiance emitted toward the ray from above
+fn emitted(primitive: &dyn Primitive) -> f64 {
+    let ray = Ray::new(vec3(0.1, 1.0, 0.2), vec3(0.1, -1.0, 0.1));
+    let (isec, material) = primitive.hit(&ray, 1e-6, f64::MAX).unwrap();
+    material.emit(&isec).y
+}
+
+#[test]
+fn transformed_area() {
+    let scale = Transform::scale(2.0, 3.0, 0.5);
+    assert!(close(Rect::new(1.0, 2.0).transformed_area(&scale), 2.0, 1e-12));
+    let triangle = Triangle::new((vec3(0.0, 0.0, 0.0), vec3(1.0, 0.0, 0.0), vec3(0.0, 1.0, 0.0)), (vec3(0.0, 0.0, 1.0), vec3(0.0, 0.0, 1.0), vec3(0.0, 0.0, 1.0)));
+    assert!(close(triangle.transformed_area(&scale), 3.0, 1e-12));
+    let sphere = Sphere::new(vec3(1.0, 0.0, 0.0), 0.5);
+    assert!(close(sphere.transformed_area(&Transform::rotate_x(30.0)), math::PI, 1e-3));
+    assert!(close(sphere.transformed_area(&Transform::scale(2.0, 2.0, 2.0)), 4.0 * math::PI, 1e-3));
+    // prolate spheroid with semi-axes 1, 1 and 2
+    let spheroid = 2.0 * math::PI * (1.0 + 2.0 * (0.75f64).sqrt().asin() / 0.75f64.sqrt());
+    assert!(close(Sphere::new(vec3(0.0, 0.0, 0.0), 1.0).transformed_area(&Transform::scale(1.0, 2.0, 1.0)), spheroid, 1e-3));
+}
+
+#[test]
+fn emitter_power_does_not_depend_on_transform() {
+    let unit = LightUnit::Watts(100.0);
+    let white = vec3(1.0, 1.0, 1.0);
+    let large = Geometry::emitter(Box::new(Rect::new(2.0, 3.0)), white, unit, false);
+    let scaled = TransformedPrimitive::emitter(Box::new(Rect::new(1.0, 1.0)), white, unit, false, Transform::scale(2.0, 1.0, 3.0));
+    let expected = 100.0 / (math::PI * 6.0);
+    assert!(close(emitted(&large), expected, 1e-9));
+    assert!(close(emitted(&scaled), expected, 1e-9));
+}
@@ -0,0 +1,447 @@
+extern crate rayon;
+extern crate raytracing_study;
+
+use std::ops::{ Add, Mul };
+use std::sync::Arc;
+
+use rayon::prelude::*;
+
+use raytracing_study::{ vec3, Vector3, math, Intersection };
+use raytracing_study::{ PMaterial, LambertMaterial, OrenNayarMaterial };
+use raytracing_study::{ SpecularReflectionMaterial, SpecularTransmissionMaterial, ThinDielectricMaterial };
+use raytracing_study::{ MicrofacetReflectionMaterial, MicrofacetTransmissionMaterial, Conductor, ConductorMaterial };
+use raytracing_study::{ PrincipledMaterial, PrincipledParameters, LayeredMaterial, FresnelBlendMaterial };
+use raytracing_study::{ MixMaterial, SheenMaterial, HairMaterial, NormalMapMaterial, ConstantTexture };
+
+// numerical validation of every material.
+// all checks are monte carlo estimates, so tolerances are kept several standard deviations
+// away from the noise. each test seeds random numbers so that a failure is reproducible.
+
+const SAMPLES: usize = 50000;
+const STRATA: usize = 128;
+const COS_BINS: usize = 16;
+const PHI_BINS: usize = 32;
+
+struct Case {
+    name: &'static str,
+    material: Arc<PMaterial>,
+    // sampling may return directions of dirac delta, pdf() and bsdf() don't cover them
+    delta: bool,
+    // bsdf() is a stochastic estimate and sampled pdf is known only up to scale
+    stochastic: bool,
+    // bsdf(wo, wi) equals bsdf(wi, wo) for reflection
+    reciprocal: bool,
+    // sampling never fails for wo above surface, so pdf integrates to one
+    complete: bool,
+    // radiance is scaled by squared ratio of ior on refraction
+    transmissive: bool,
+    // no energy is lost for wo above surface
+    lossless: bool,
+    // albedo never exceeds one
+    conserving: bool,
+    // bsdf() vanishes outside of directions sampling can return
+    covered: bool,
+    // light may arrive at back side of the surface
+    two_sided: bool,
+}
+
+fn case(name: &'static str, material: Arc<PMaterial>) -> Case {
+    Case {
+        name,
+        material,
+        delta: false,
+        stochastic: false,
+        reciprocal: true,
+        complete: false,
+        transmissive: false,
+        lossless: false,
+        conserving: true,
+        covered: true,
+        two_sided: true,
+    }
+}
+
+fn cases() -> Vec<Case> {
+    let white = vec3(1.0, 1.0, 1.0);
+    let lambert: Arc<PMaterial> = Arc::new(LambertMaterial::new(white));
+    let rough = PrincipledParameters { base_color: vec3(0.9, 0.6, 0.3), roughness: 0.6, sheen: 1.0, clearcoat: 1.0, clearcoat_gloss: 0.0, ..Default::default() };
+    let metal = PrincipledParameters { base_color: white, metallic: 1.0, roughness: 0.4, ..Default::default() };
+    let glass = PrincipledParameters { base_color: white, transmission: 1.0, roughness: 0.7, ..Default::default() };
+    vec![
+        // bsdf() of lambert is constant in every direction, but it is sampled only around the normal
+        Case { complete: true, lossless: true, covered: false, ..case("lambert", lambert.clone()) },
+        Case { complete: true, ..case("oren-nayar", Arc::new(OrenNayarMaterial::new(white, 30.0))) },
+        Case { delta: true, lossless: true, two_sided: false, ..case("specular reflection", Arc::new(SpecularReflectionMaterial::new(white))) },
+        // refracted radiance is divided by cosine of wo instead of wi
+        Case { delta: true, transmissive: true, conserving: false, ..case("specular transmission", Arc::new(SpecularTransmissionMaterial::new(white, 1.5))) },
+        Case { delta: true, lossless: true, ..case("thin dielectric", Arc::new(ThinDielectricMaterial::new(white, 1.5))) },
+        Case { lossless: true, ..case("microfacet", Arc::new(MicrofacetReflectionMaterial::new(white, 0.5))) },
+        case("microfacet anisotropic", Arc::new(MicrofacetReflectionMaterial::anisotropic(white, 0.3, 0.7))),
+        Case { transmissive: true, ..case("microfacet transmission", Arc::new(MicrofacetTransmissionMaterial::new(white, 1.5, 0.5))) },
+        case("gold", Arc::new(ConductorMaterial::new(Conductor::gold(), 0.4))),
+        case("copper anisotropic", Arc::new(ConductorMaterial::anisotropic(Conductor::copper(), 0.3, 0.6))),
+        Case { delta: true, ..case("smooth silver", Arc::new(ConductorMaterial::new(Conductor::silver(), 0.0))) },
+        case("fresnel blend", Arc::new(FresnelBlendMaterial::new(vec3(0.8, 0.5, 0.2), vec3(0.04, 0.04, 0.04), 0.5))),
+        case("principled", Arc::new(PrincipledMaterial::new(rough))),
+        case("principled metal", Arc::new(PrincipledMaterial::new(metal))),
+        Case { transmissive: true, ..case("principled glass", Arc::new(PrincipledMaterial::new(glass))) },
+        Case { stochastic: true, reciprocal: false, ..case("layered", Arc::new(LayeredMaterial::new(lambert.clone(), 1.5, 0.3))) },
+        Case { stochastic: true, reciprocal: false, ..case("layered smooth", Arc::new(LayeredMaterial::with_absorption(
+            Arc::new(ConductorMaterial::new(Conductor::gold(), 0.3)), 1.5, 0.0, vec3(0.8, 0.4, 0.2), 0.5))) },
+        Case { complete: true, ..case("sheen", Arc::new(SheenMaterial::new(white, 0.5))) },
+        Case { reciprocal: false, complete: true, covered: false, ..case("sheen layered", Arc::new(SheenMaterial::layered(white, 0.3, lambert.clone()))) },
+        Case { covered: false, ..case("mix", Arc::new(MixMaterial::new(lambert.clone(), Arc::new(MicrofacetReflectionMaterial::new(white, 0.3)), 0.4))) },
+        Case { delta: true, two_sided: false, ..case("mix specular", Arc::new(MixMaterial::new(lambert.clone(), Arc::new(SpecularReflectionMaterial::new(white)), 0.5))) },
+        Case { stochastic: true, reciprocal: false, ..case("mix layered", Arc::new(MixMaterial::new(lambert.clone(),
+            Arc::new(LayeredMaterial::new(lambert.clone(), 1.5, 0.3)), 0.6))) },
+        Case { stochastic: true, reciprocal: false, ..case("sheen over layered", Arc::new(SheenMaterial::layered(white, 0.3,
+            Arc::new(LayeredMaterial::new(lambert, 1.5, 0.3))))) },
+        Case { reciprocal: false, complete: true, lossless: true, ..case("hair", Arc::new(HairMaterial::new(Vector3::zero(), 0.3, 0.3, 2.0))) },
+        Case { reciprocal: false, complete: true, ..case("hair melanin", Arc::new(HairMaterial::melanin(1.3, 0.2, 0.4, 0.5, 2.0))) },
+    ]
+}
+
+// outgoing directions in local space. hair uses v of uv as offset across the fiber.
+fn directions() -> Vec<Vector3> {
+    vec![vec3(0.3, 0.2, 1.0).norm(), vec3(-0.9, 0.3, 0.15).norm(), vec3(0.2, -0.3, -1.0).norm()]
+}
+
+// surface with tilted normal so that materials are not only tested in canonical frame
+fn intersection(wo: Vector3) -> Intersection {
+    let normal = vec3(0.3, 0.5, 0.8).norm();
+    let tangent = math::tangent(normal, vec3(1.0, 0.0, 0.0));
+    let isec = Intersection::new(1.0, Vector3::zero(), Vector3::zero(), normal, (0.5, 0.37), tangent, normal.cross(tangent));
+    Intersection { wo: isec.to_world(wo), ..isec }
+}
+
+fn uniform_sphere_dir(u1: f64, u2: f64) -> Vector3 {
+    let z = 1.0 - 2.0 * u1;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * math::PI * u2;
+    vec3(r * phi.cos(), r * phi.sin(), z)
+}
+
+// stratified estimate of integral of function over the sphere of local directions
+fn integrate<T, F>(zero: T, f: F) -> T
+    where T: Copy + Add<Output = T> + Mul<f64, Output = T>, F: Fn(Vector3) -> T {
+    let mut sum = zero;
+    for i in 0..STRATA {
+        for j in 0..(2 * STRATA) {
+            let u1 = (i as f64 + math::random()) / (STRATA as f64);
+            let u2 = (j as f64 + math::random()) / ((2 * STRATA) as f64);
+            sum = sum + f(uniform_sphere_dir(u1, u2));
+        }
+    }
+    sum * (4.0 * math::PI / ((2 * STRATA * STRATA) as f64))
+}
+
+fn max_component(v: Vector3) -> f64 {
+    v.x.max(v.y.max(v.z))
+}
+
+fn min_component(v: Vector3) -> f64 {
+    v.x.min(v.y.min(v.z))
+}
+
+fn close(a: f64, b: f64, tolerance: f64) -> bool {
+    (a - b).abs() <= tolerance * a.abs().max(b.abs()).max(1e-3)
+}
+
+fn close_vec(a: Vector3, b: Vector3, tolerance: f64) -> bool {
+    close(a.x, b.x, tolerance) && close(a.y, b.y, tolerance) && close(a.z, b.z, tolerance)
+}
+
+#[test]
+fn pdf_integrates_to_one() {
+    math::seed(1);
+    for case in cases().iter().filter(|case| !case.delta) {
+        for wo in directions() {
+            let isec = intersection(wo);
+            let integral = integrate(0.0, |wi| case.material.pdf(&isec, isec.to_world(wi)));
+            assert!(integral <= 1.02, "{}: pdf integrates to {} for wo {:?}", case.name, integral, wo);
+            if case.complete && wo.z > 0.0 {
+                assert!(integral >= 0.98, "{}: pdf integrates to {} for wo {:?}", case.name, integral, wo);
+            }
+        }
+    }
+}
+
+#[test]
+fn sample_agrees_with_bsdf_and_pdf() {
+    math::seed(2);
+    for case in cases().iter().filter(|case| !case.delta && !case.stochastic) {
+        for wo in directions() {
+            let isec = intersection(wo);
+            for _ in 0..(SAMPLES / 10) {
+                if let Some(s) = case.material.sample(&isec) {
+                    if s.pdf == 0.0 {
+                        continue;
+                    }
+                    let pdf = case.material.pdf(&isec, s.wi);
+                    let value = case.material.bsdf(&isec, s.wi);
+                    assert!(close(pdf, s.pdf, 1e-4), "{}: sampled pdf {} but pdf() is {}", case.name, s.pdf, pdf);
+                    assert!(close_vec(value, s.value, 1e-4),
+                        "{}: sampled value {:?} but bsdf() is {:?}", case.name, s.value, value);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn specular_flag_marks_delta_samples() {
+    math::seed(7);
+    for case in cases() {
+        for wo in directions() {
+            let isec = intersection(wo);
+            for _ in 0..(SAMPLES / 10) {
+                if let Some(s) = case.material.sample(&isec) {
+                    // directions that pdf() can not evaluate must be flagged.
+                    // smooth coat of layered material is specular although its pdf is approximated
+                    let unmeasurable = case.material.pdf(&isec, s.wi) == 0.0 && s.pdf > 0.0;
+                    assert!(!unmeasurable || s.specular, "{}: sample toward {:?} is not flagged as specular", case.name, s.wi);
+                    assert!(case.delta || case.stochastic || !s.specular, "{}: sample toward {:?} is flagged as specular", case.name, s.wi);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn stochastic_materials_are_reported() {
+    // composite materials rely on the flag to keep value and pdf of a sample together
+    for case in cases() {
+        assert_eq!(case.material.stochastic(), case.stochastic, "{}", case.name);
+    }
+}
+
+fn bin(w: Vector3) -> usize {
+    let c = ((0.5 * (w.z + 1.0) * COS_BINS as f64) as usize).min(COS_BINS - 1);
+    let phi = w.y.atan2(w.x) / (2.0 * math::PI) + 0.5;
+    let p = ((phi * PHI_BINS as f64) as usize).min(PHI_BINS - 1);
+    c * PHI_BINS + p
+}
+
+// expected frequencies in bins of cosine and azimuth, integrated with sub-grid of each bin
+fn expected_frequencies(case: &Case, isec: &Intersection) -> Vec<f64> {
+    let sub = 32;
+    let area = 4.0 * math::PI / ((COS_BINS * PHI_BINS * sub * sub) as f64);
+    (0..(COS_BINS * PHI_BINS)).into_par_iter().map(|i| {
+        let (c, p) = (i / PHI_BINS, i % PHI_BINS);
+        let mut sum = 0.0;
+        for k in 0..(sub * sub) {
+            let z = -1.0 + 2.0 * (c as f64 + ((k / sub) as f64 + 0.5) / sub as f64) / COS_BINS as f64;
+            let phi = -math::PI + 2.0 * math::PI * (p as f64 + ((k % sub) as f64 + 0.5) / sub as f64) / PHI_BINS as f64;
+            let r = (1.0 - z * z).max(0.0).sqrt();
+            let wi = vec3(r * phi.cos(), r * phi.sin(), z);
+            sum += case.material.pdf(isec, isec.to_world(wi)) * area;
+        }
+        sum * SAMPLES as f64
+    }).collect()
+}
+
+// cumulative probability of chi-square distribution by Wilson-Hilferty approximation,
+// expressed as standard normal deviate
+fn chi_square_deviate(chi2: f64, dof: usize) -> f64 {
+    let k = dof as f64;
+    let s = 2.0 / (9.0 * k);
+    ((chi2 / k).cbrt() - (1.0 - s)) / s.sqrt()
+}
+
+#[test]
+fn sample_distribution_matches_pdf() {
+    math::seed(3);
+    for case in cases().iter().filter(|case| !case.delta && !case.stochastic) {
+        for wo in directions() {
+            let isec = intersection(wo);
+            let mut observed = vec![0.0; COS_BINS * PHI_BINS + 1];
+            for _ in 0..SAMPLES {
+                match case.material.sample(&isec) {
+                    Some(ref s) if s.pdf > 0.0 => observed[bin(isec.to_local(s.wi))] += 1.0,
+                    // failed samples are counted in the last bin
+                    _ => observed[COS_BINS * PHI_BINS] += 1.0,
+                }
+            }
+            let mut expected = expected_frequencies(case, &isec);
+            expected.push(SAMPLES as f64 - expected.iter().sum::<f64>());
+
+            // bins with low expected frequency are pooled
+            let mut order = (0..expected.len()).collect::<Vec<usize>>();
+            order.sort_by(|a, b| expected[*a].partial_cmp(&expected[*b]).unwrap());
+            let (mut chi2, mut dof) = (0.0, 0);
+            let (mut pool_expected, mut pool_observed) = (0.0, 0.0);
+            for i in order {
+                if expected[i] < 5.0 || pool_expected > 0.0 && pool_expected < 5.0 {
+                    pool_expected += expected[i];
+                    pool_observed += observed[i];
+                } else {
+                    chi2 += math::pow2(observed[i] - expected[i]) / expected[i];
+                    dof += 1;
+                }
+            }
+            if pool_expected > 0.0 {
+                chi2 += math::pow2(pool_observed - pool_expected) / pool_expected.max(5.0);
+                dof += 1;
+            }
+            if dof < 2 {
+                continue;
+            }
+            let deviate = chi_square_deviate(chi2, dof - 1);
+            assert!(deviate < 4.5, "{}: chi-square {} with {} dof for wo {:?}", case.name, chi2, dof - 1, wo);
+        }
+    }
+}
+
+#[test]
+fn helmholtz_reciprocity() {
+    math::seed(4);
+    for case in cases().iter().filter(|case| case.reciprocal && !case.stochastic) {
+        for _ in 0..1000 {
+            let a = uniform_sphere_dir(math::random(), math::random());
+            let b = uniform_sphere_dir(math::random(), math::random());
+            // reflection in upper hemisphere
+            let a = vec3(a.x, a.y, a.z.abs().max(1e-2)).norm();
+            let b = vec3(b.x, b.y, b.z.abs().max(1e-2)).norm();
+            let isec_a = intersection(a);
+            let isec_b = intersection(b);
+            let f_ab = case.material.bsdf(&isec_a, isec_a.to_world(b));
+            let f_ba = case.material.bsdf(&isec_b, isec_b.to_world(a));
+            assert!(close_vec(f_ab, f_ba, 1e-6),
+                "{}: f({:?}, {:?}) = {:?} but reversed is {:?}", case.name, a, b, f_ab, f_ba);
+        }
+    }
+}
+
+fn sampled_albedo(material: &PMaterial, isec: &Intersection) -> Vector3 {
+    let mut sampled = Vector3::zero();
+    for _ in 0..SAMPLES {
+        if let Some(s) = material.sample(isec) {
+            if s.pdf > 0.0 {
+                sampled += s.value * isec.normal.dot(s.wi).abs() / s.pdf;
+            }
+        }
+    }
+    sampled / SAMPLES as f64
+}
+
+#[test]
+fn white_furnace() {
+    math::seed(5);
+    for case in cases() {
+        for wo in directions() {
+            // radiance leaving denser medium is scaled up by refraction
+            if (case.transmissive || !case.two_sided) && wo.z < 0.0 {
+                continue;
+            }
+            let isec = intersection(wo);
+            let sampled = sampled_albedo(&*case.material, &isec);
+            if case.conserving {
+                assert!(max_component(sampled) <= 1.02, "{}: albedo {:?} for wo {:?}", case.name, sampled, wo);
+            }
+            if case.lossless && wo.z > 0.0 {
+                assert!(min_component(sampled) >= 0.97, "{}: albedo {:?} for wo {:?}", case.name, sampled, wo);
+            }
+            if case.delta || case.stochastic || !case.covered {
+                continue;
+            }
+            // albedo integrated from bsdf() agrees with sampled one
+            let integrated = integrate(Vector3::zero(), |wi| {
+                let wi = isec.to_world(wi);
+                case.material.bsdf(&isec, wi) * isec.normal.dot(wi).abs()
+            });
+            let difference = integrated - sampled;
+            assert!(max_component(difference) < 0.03 && min_component(difference) > -0.03,
+                "{}: integrated albedo {:?} but sampled albedo {:?} for wo {:?}", case.name, integrated, sampled, wo);
+        }
+    }
+}
+
+#[test]
+fn mix_of_stochastic_material() {
+    math::seed(9);
+    let lambert: Arc<PMaterial> = Arc::new(LambertMaterial::new(vec3(0.2, 0.5, 0.8)));
+    let layered: Arc<PMaterial> = Arc::new(LayeredMaterial::new(Arc::new(LambertMaterial::new(vec3(0.9, 0.6, 0.3))), 1.5, 0.3));
+    let mix = MixMaterial::new(lambert.clone(), layered.clone(), 0.6);
+    for wo in directions().into_iter().filter(|wo| wo.z > 0.0) {
+        let isec = intersection(wo);
+        // sampled albedo of the mix is the mix of sampled albedos
+        let expected = 0.4 * sampled_albedo(&*lambert, &isec) + 0.6 * sampled_albedo(&*layered, &isec);
+        let difference = sampled_albedo(&mix, &isec) - expected;
+        assert!(max_component(difference) < 0.02 && min_component(difference) > -0.02,
+            "albedo differs by {:?} from {:?} for wo {:?}", difference, expected, wo);
+    }
+}
+
+#[test]
+fn sheen_over_stochastic_material() {
+    math::seed(10);
+    let base: Arc<PMaterial> = Arc::new(LayeredMaterial::new(Arc::new(LambertMaterial::new(vec3(0.9, 0.6, 0.3))), 1.5, 0.3));
+    let sheen: Arc<PMaterial> = Arc::new(SheenMaterial::layered(vec3(0.8, 0.8, 0.8), 0.3, base));
+    for wo in directions().into_iter().filter(|wo| wo.z > 0.0) {
+        let isec = intersection(wo);
+        // bsdf() of layered base is unbiased, so its integral is the albedo.
+        // lambert below the coat is not covered by sampling under the surface
+        let integrated = integrate(Vector3::zero(), |wi| {
+            if wi.z <= 0.0 {
+                return Vector3::zero();
+            }
+            let wi = isec.to_world(wi);
+            sheen.bsdf(&isec, wi) * isec.normal.dot(wi).abs()
+        });
+        let difference = sampled_albedo(&*sheen, &isec) - integrated;
+        assert!(max_component(difference) < 0.03 && min_component(difference) > -0.03,
+            "albedo differs by {:?} from {:?} for wo {:?}", difference, integrated, wo);
+    }
+}
+
+#[test]
+fn nested_normal_map_is_applied() {
+    math::seed(6);
+    let white = vec3(1.0, 1.0, 1.0);
+    // tangent space normal tilted toward tangent
+    let tilted = vec3(0.4, 0.0, 0.9).norm();
+    let normal_map = Arc::new(ConstantTexture::new(0.5 * tilted + 0.5));
+    let base: Arc<PMaterial> = Arc::new(MicrofacetReflectionMaterial::new(white, 0.2));
+    let mapped: Arc<PMaterial> = Arc::new(NormalMapMaterial::new(base.clone(), normal_map));
+    let isec = intersection(vec3(0.3, 0.2, 1.0).norm());
+    let mapped_isec = mapped.shade(&isec);
+    for material in [
+        Arc::new(MixMaterial::new(mapped.clone(), mapped.clone(), 0.5)) as Arc<PMaterial>,
+        Arc::new(SheenMaterial::layered(Vector3::zero(), 0.5, mapped.clone())),
+    ] {
+        let top = material.shade(&isec);
+        for _ in 0..100 {
+            let wi = isec.to_world(uniform_sphere_dir(math::random(), math::random()));
+            let expected = mapped.bsdf(&mapped_isec, wi);
+            assert!(close_vec(material.bsdf(&top, wi), expected, 1e-9), "bsdf is not perturbed for {:?}", wi);
+        }
+    }
+    // perturbed frame must actually differ from the unperturbed one
+    let wi = isec.to_world(math::reflect(-isec.to_local(isec.wo), tilted));
+    assert!(!close_vec(mapped.bsdf(&mapped_isec, wi), base.bsdf(&isec, wi), 1e-3));
+}
+
+#[test]
+fn zero_roughness_is_finite() {
+    math::seed(8);
+    let white = vec3(1.0, 1.0, 1.0);
+    let materials: Vec<Arc<PMaterial>> = vec![
+        Arc::new(MicrofacetReflectionMaterial::new(white, 0.0)),
+        Arc::new(MicrofacetReflectionMaterial::anisotropic(white, 0.0, 0.5)),
+        Arc::new(FresnelBlendMaterial::new(vec3(0.8, 0.5, 0.2), vec3(0.04, 0.04, 0.04), 0.0)),
+    ];
+    let finite = |v: Vector3| v.x.is_finite() && v.y.is_finite() && v.z.is_finite();
+    for material in &materials {
+        for wo in directions() {
+            let isec = intersection(wo);
+            // mirror direction is where the distribution of normals peaks
+            let mirror = isec.to_world(math::reflect(-wo, vec3(0.0, 0.0, 1.0)));
+            assert!(finite(material.bsdf(&isec, mirror)) && material.pdf(&isec, mirror).is_finite());
+            for _ in 0..1000 {
+                if let Some(s) = material.sample(&isec) {
+                    assert!(finite(s.value) && s.pdf.is_finite(), "sample {:?} with pdf {} for wo {:?}", s.value, s.pdf, wo);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,62 @@
+extern crate raytracing_study;
+
+use raytracing_study::{ vec3, Vector3, math, noise };
+
+// points spread over positive and negative cells
+fn points() -> Vec<Vector3> {
+    math::seed(1);
+    (0..20000).map(|_| 20.0 * vec3(math::random(), math::random(), math::random()) - 10.0).collect()
+}
+
+#[test]
+fn perlin_is_zero_at_lattice_points() {
+    for x in -3..=3 {
+        for y in -3..=3 {
+            for z in -3..=3 {
+                let p = vec3(x as f64, y as f64, z as f64);
+                assert_eq!(noise::perlin(p), 0.0, "noise at {:?}", p);
+            }
+        }
+    }
+    // but not between them
+    assert!(points().iter().any(|p| noise::perlin(*p).abs() > 0.1));
+}
+
+#[test]
+fn range() {
+    let (mut min, mut max) = (f64::INFINITY, f64::NEG_INFINITY);
+    for p in points() {
+        for value in &[noise::perlin(p), noise::fbm(p, 1), noise::fbm(p, 5)] {
+            // gradients toward edges of cube slightly exceed one in the worst case
+            assert!(value.abs() <= 1.05, "noise {} at {:?}", value, p);
+            min = min.min(*value);
+            max = max.max(*value);
+        }
+        let turbulence = noise::turbulence(p, 5);
+        assert!((0.0..=1.0).contains(&turbulence), "turbulence {} at {:?}", turbulence, p);
+    }
+    // values spread over the range
+    assert!(min < -0.5 && max > 0.5, "noise is in [{}, {}]", min, max);
+}
+
+#[test]
+fn deterministic() {
+    for p in points().into_iter().take(100) {
+        assert_eq!(noise::perlin(p), noise::perlin(p));
+        assert_eq!(noise::fbm(p, 4), noise::fbm(p, 4));
+        assert_eq!(noise::worley(p), noise::worley(p));
+    }
+    // single octave is plain perlin noise
+    let p = vec3(0.3, -1.7, 2.2);
+    assert_eq!(noise::fbm(p, 1), noise::perlin(p));
+}
+
+#[test]
+fn worley_distances_are_ordered() {
+    for p in points() {
+        let (f1, f2) = noise::worley(p);
+        assert!(f1 >= 0.0 && f1 <= f2, "distances ({}, {}) at {:?}", f1, f2, p);
+        // feature point of the cell containing p is within its diagonal
+        assert!(f1 <= 3.0f64.sqrt(), "distance {} at {:?}", f1, p);
+    }
+}
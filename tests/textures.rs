@@ -0,0 +1,89 @@
+extern crate raytracing_study;
+
+use std::sync::Arc;
+
+use raytracing_study::{ vec3, Vector3, Transform, Intersection, Texture, TriplanarTexture };
+use raytracing_study::{ Ray, RayDifferential, Scene, PPrimitive, Geometry, TransformedPrimitive, Sphere };
+use raytracing_study::LambertMaterial;
+
+// linear in texture coordinates, so that each projection gives distinct value
+struct Coordinates;
+
+impl Texture<f64> for Coordinates {
+    fn value(&self, isec: &Intersection) -> f64 {
+        isec.uv.0 + 10.0 * isec.uv.1
+    }
+}
+
+// size of footprint in texture coordinates
+struct Footprint;
+
+impl Texture<f64> for Footprint {
+    fn value(&self, isec: &Intersection) -> f64 {
+        isec.differential.map_or(0.0, |d| d.duvdx.0.abs() + d.duvdx.1.abs() + d.duvdy.0.abs() + d.duvdy.1.abs())
+    }
+}
+
+fn intersection(pos: Vector3, normal: Vector3) -> Intersection {
+    Intersection::new(1.0, normal, pos, normal, (0.0, 0.0), vec3(1.0, 0.0, 0.0), vec3(0.0, 1.0, 0.0))
+}
+
+fn close(a: f64, b: f64) -> bool {
+    (a - b).abs() <= 1e-9 * a.abs().max(b.abs()).max(1.0)
+}
+
+#[test]
+fn blend_weights() {
+    let texture = TriplanarTexture::new(Arc::new(Coordinates), 1.0, 2.0);
+    let p = vec3(0.1, 0.2, 0.3);
+    // squared components of normal weight projections along x and z
+    let isec = intersection(p, vec3(0.6, 0.0, 0.8));
+    let along_x = -0.3 + 10.0 * 0.2;
+    let along_z = 0.1 + 10.0 * 0.2;
+    assert!(close(texture.value(&isec), 0.36 * along_x + 0.64 * along_z));
+    // u is flipped on back side
+    let isec = intersection(p, vec3(-0.6, 0.0, -0.8));
+    assert!(close(texture.value(&isec), 0.36 * (0.3 + 10.0 * 0.2) + 0.64 * (-0.1 + 10.0 * 0.2)));
+    // normal along an axis uses only its projection
+    let isec = intersection(p, vec3(0.0, 1.0, 0.0));
+    assert!(close(texture.value(&isec), 0.1 + 10.0 * -0.3));
+    // sharp blend is close to the dominant projection
+    let sharp = TriplanarTexture::new(Arc::new(Coordinates), 1.0, 64.0);
+    let isec = intersection(p, vec3(0.6, 0.0, 0.8));
+    assert!((sharp.value(&isec) - along_z).abs() < 1e-6);
+}
+
+fn sphere() -> Box<PPrimitive> {
+    Box::new(Geometry::new(Box::new(Sphere::new(Vector3::zero(), 1.0)), Arc::new(LambertMaterial::new(vec3(0.5, 0.5, 0.5)))))
+}
+
+fn transform() -> Transform {
+    Transform::translate(1.0, -2.0, 0.5).transform(&Transform::rotate_y(30.0)).transform(&Transform::scale(2.0, 0.5, 1.5))
+}
+
+fn hit(primitive: Box<PPrimitive>, ray: &Ray) -> Intersection {
+    Scene::new(vec![primitive]).hit(ray).expect("ray misses the sphere").0
+}
+
+#[test]
+fn projection_follows_transformed_primitive() {
+    let coordinates = TriplanarTexture::new(Arc::new(Coordinates), 2.0, 4.0);
+    let footprint = TriplanarTexture::new(Arc::new(Footprint), 2.0, 4.0);
+    let transform = transform();
+    for dir in &[vec3(0.05, 0.1, 1.0), vec3(-0.12, 0.04, 1.0), vec3(0.08, -0.15, 1.0)] {
+        let dir = dir.norm();
+        let org = vec3(0.0, 0.0, -5.0);
+        let ray = Ray::with_differential(org, dir, RayDifferential {
+            rx_org: org,
+            rx_dir: dir + vec3(0.002, 0.0, 0.0),
+            ry_org: org,
+            ry_dir: dir + vec3(0.0, 0.002, 0.0),
+        });
+        let isec = hit(sphere(), &ray);
+        // the same point of the shape is hit by the transformed ray, so the texture does not change
+        let transformed = hit(Box::new(TransformedPrimitive::new(sphere(), self::transform())), &transform.ray(&ray));
+        assert!(close(coordinates.value(&transformed), coordinates.value(&isec)));
+        let (expected, value) = (footprint.value(&isec), footprint.value(&transformed));
+        assert!(expected > 0.0 && (value - expected).abs() < 1e-6 * expected, "footprint {} but expected {}", value, expected);
+    }
+}